@@ -90,7 +90,7 @@ pub fn transform(item: TokenStream) -> TokenStream {
     fn_item.sig.inputs =
         Parser::parse2(Punctuated::<FnArg, Token![,]>::parse_terminated, new_args).unwrap();
     fn_item.sig.output = parse_macro_input!(new_return as ReturnType);
-    fn_item.block = Box::new(parse_macro_input!(new_block as Block));
+    *fn_item.block = parse_macro_input!(new_block as Block);
 
     fn_item.to_token_stream().into()
 }
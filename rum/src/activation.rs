@@ -0,0 +1,398 @@
+//! Socket activation and handoff of listening sockets between processes.
+
+use crate::listener::{Addr, Listener, PeerInfo};
+use command_fds::{CommandFdExt, FdMapping};
+use nix::sys::socket::{getsockname, getsockopt, sockopt::AcceptConn, SockaddrStorage};
+use socket2::SockRef;
+use std::env::VarError;
+use std::fmt::Debug;
+use std::io;
+use std::os::fd::{AsFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::process::{Child, Command};
+use std::sync::{Mutex, PoisonError};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+
+/// The first file descriptor passed to a process using socket activation.
+const LISTEN_FDS_START: i32 = 3;
+
+/// The passed file descriptors taken so far, so that none is owned twice.
+static TAKEN_FDS: Mutex<Vec<RawFd>> = Mutex::new(Vec::new());
+
+/// The name given to listeners passed without a name.
+const DEFAULT_LISTENER_NAME: &str = "unknown";
+
+/// Creates the error returned for file descriptors that are not listening
+/// stream sockets.
+fn not_a_listener() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "the file descriptor is not a listening TCP or Unix domain socket",
+    )
+}
+
+/// A listening socket created outside of the server, e.g. by systemd or by a
+/// previous instance of the server, which can be either a TCP or a Unix
+/// domain socket.
+#[derive(Debug)]
+pub enum InheritedListener {
+    /// A TCP listener.
+    Tcp(TcpListener),
+    /// A Unix domain socket listener.
+    Unix(UnixListener),
+}
+
+impl InheritedListener {
+    /// Takes ownership of an open listening socket. Sockets are usually
+    /// passed by number, and converted using `OwnedFd::from_raw_fd`, which
+    /// requires that nothing else owns the descriptor. Listeners passed using
+    /// socket activation should be taken with [`InheritedListeners`] instead.
+    ///
+    /// This must be called within a Tokio runtime.
+    pub fn from_fd(fd: OwnedFd) -> io::Result<Self> {
+        if !getsockopt(&fd, AcceptConn).map_err(|_| not_a_listener())? {
+            return Err(not_a_listener());
+        }
+
+        let listener = std::net::TcpListener::from(fd);
+
+        if listener.local_addr().is_ok() {
+            listener.set_nonblocking(true)?;
+            return Ok(Self::Tcp(TcpListener::from_std(listener)?));
+        }
+
+        let listener = std::os::unix::net::UnixListener::from(OwnedFd::from(listener));
+
+        if listener.local_addr().is_ok() {
+            listener.set_nonblocking(true)?;
+            return Ok(Self::Unix(UnixListener::from_std(listener)?));
+        }
+
+        Err(not_a_listener())
+    }
+}
+
+impl AsFd for InheritedListener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            Self::Tcp(listener) => listener.as_fd(),
+            Self::Unix(listener) => listener.as_fd(),
+        }
+    }
+}
+
+impl Listener for InheritedListener {
+    type Io = InheritedStream;
+
+    async fn accept(&mut self) -> io::Result<(Self::Io, PeerInfo)> {
+        match self {
+            Self::Tcp(listener) => Listener::accept(listener)
+                .await
+                .map(|(conn, peer)| (InheritedStream::Tcp(conn), peer)),
+            Self::Unix(listener) => Listener::accept(listener)
+                .await
+                .map(|(conn, peer)| (InheritedStream::Unix(conn), peer)),
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Addr> {
+        match self {
+            Self::Tcp(listener) => Listener::local_addr(listener),
+            Self::Unix(listener) => Listener::local_addr(listener),
+        }
+    }
+}
+
+/// A connection accepted by an [`InheritedListener`].
+#[derive(Debug)]
+pub enum InheritedStream {
+    /// A TCP connection.
+    Tcp(TcpStream),
+    /// A Unix domain socket connection.
+    Unix(UnixStream),
+}
+
+impl AsyncRead for InheritedStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(conn) => Pin::new(conn).poll_read(cx, buf),
+            Self::Unix(conn) => Pin::new(conn).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for InheritedStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(conn) => Pin::new(conn).poll_write(cx, buf),
+            Self::Unix(conn) => Pin::new(conn).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(conn) => Pin::new(conn).poll_write_vectored(cx, bufs),
+            Self::Unix(conn) => Pin::new(conn).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Self::Tcp(conn) => conn.is_write_vectored(),
+            Self::Unix(conn) => conn.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(conn) => Pin::new(conn).poll_flush(cx),
+            Self::Unix(conn) => Pin::new(conn).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(conn) => Pin::new(conn).poll_shutdown(cx),
+            Self::Unix(conn) => Pin::new(conn).poll_shutdown(cx),
+        }
+    }
+}
+
+/// The listening sockets passed to this process using the systemd socket
+/// activation protocol, through the `LISTEN_FDS`, `LISTEN_PID` and
+/// `LISTEN_FDNAMES` environment variables. This is how systemd socket units,
+/// and servers handing off their listeners with [`ListenerHandoff`], pass
+/// sockets on. If `LISTEN_PID` is not set, the sockets are accepted
+/// regardless of which process they were meant for. Each socket can only be
+/// taken once per process.
+pub struct InheritedListeners {
+    /// The number of passed sockets.
+    len: usize,
+    /// The names of the passed sockets, in order.
+    names: Vec<String>,
+}
+
+impl InheritedListeners {
+    /// Reads the sockets passed to this process from the environment, which
+    /// is left unchanged. This can be called at any time, including within a
+    /// Tokio runtime.
+    pub fn from_env() -> Self {
+        let for_this_process = match std::env::var("LISTEN_PID") {
+            Ok(pid) if !pid.is_empty() => pid.parse() == Ok(std::process::id()),
+            Ok(_) | Err(VarError::NotPresent) => true,
+            Err(VarError::NotUnicode(_)) => false,
+        };
+        let len = std::env::var("LISTEN_FDS")
+            .ok()
+            .filter(|_| for_this_process)
+            .and_then(|len| len.parse().ok())
+            .unwrap_or(0);
+        let names = std::env::var("LISTEN_FDNAMES")
+            .ok()
+            .filter(|_| len > 0)
+            .map(|names| names.split(':').map(ToOwned::to_owned).collect())
+            .unwrap_or_default();
+
+        Self { len, names }
+    }
+
+    /// Reads the sockets passed to this process like
+    /// [`from_env`](Self::from_env), then removes `LISTEN_FDS`, `LISTEN_PID`
+    /// and `LISTEN_FDNAMES` from the environment, so that they are not passed
+    /// on to child processes.
+    ///
+    /// Modifying the environment while other threads may be reading it is
+    /// undefined behavior, so this must be called before the Tokio runtime
+    /// or any other threads are started, e.g. at the very start of `main`
+    /// without `#[tokio::main]`.
+    pub fn take_from_env() -> Self {
+        let listeners = Self::from_env();
+
+        for var in ["LISTEN_FDS", "LISTEN_PID", "LISTEN_FDNAMES"] {
+            std::env::remove_var(var);
+        }
+
+        listeners
+    }
+
+    /// Gets the number of sockets passed to this process, including those
+    /// already taken.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Checks whether no sockets were passed to this process.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets the name of the socket at an index, as configured with
+    /// `FileDescriptorName=` in a systemd socket unit. Sockets without a name
+    /// are named `unknown`.
+    pub fn name(&self, index: usize) -> Option<&str> {
+        if index >= self.len() {
+            return None;
+        }
+
+        Some(
+            self.names
+                .get(index)
+                .map(String::as_str)
+                .filter(|name| !name.is_empty())
+                .unwrap_or(DEFAULT_LISTENER_NAME),
+        )
+    }
+
+    /// Takes the socket at an index. This returns `None` if there is no
+    /// socket at the index, or if it has already been taken, and an error if
+    /// it is not a listening TCP or Unix domain socket, in which case it is
+    /// closed.
+    ///
+    /// This must be called within a Tokio runtime.
+    pub fn take(&mut self, index: usize) -> io::Result<Option<InheritedListener>> {
+        let Some(fd) = (index < self.len)
+            .then(|| i32::try_from(index).ok())
+            .flatten()
+            .and_then(|index| LISTEN_FDS_START.checked_add(index))
+        else {
+            return Ok(None);
+        };
+
+        let mut taken = TAKEN_FDS.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if taken.contains(&fd) {
+            return Ok(None);
+        }
+
+        getsockname::<SockaddrStorage>(fd).map_err(|_| not_a_listener())?;
+        taken.push(fd);
+        drop(taken);
+
+        // SAFETY: the descriptor was passed to this process to own, it is an
+        // open socket, and it has not been taken before, so nothing else in
+        // the process owns it.
+        #[allow(unsafe_code)]
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        SockRef::from(&fd).set_cloexec(true)?;
+
+        InheritedListener::from_fd(fd).map(Some)
+    }
+
+    /// Takes the first socket with the given name that has not yet been
+    /// taken.
+    ///
+    /// This must be called within a Tokio runtime.
+    pub fn take_named(&mut self, name: &str) -> io::Result<Option<InheritedListener>> {
+        for index in 0..self.len() {
+            if self.name(index) == Some(name) {
+                if let Some(listener) = self.take(index)? {
+                    return Ok(Some(listener));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl Debug for InheritedListeners {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InheritedListeners")
+            .field("len", &self.len())
+            .field("names", &self.names)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Hands listening sockets off to a new process, e.g. a newer version of the
+/// server, for restarts without downtime. The new process is started with the
+/// sockets using the socket activation protocol, so it can take them with
+/// [`InheritedListeners`], or with
+/// [`Server::serve_inherited`](crate::server::Server::serve_inherited).
+///
+/// Once the new process has been spawned, the old server should be shut down
+/// gracefully. The sockets stay open as long as either process holds them, so
+/// connections made in the meantime wait to be accepted by the new process
+/// rather than being refused, while the old server drains the connections it
+/// already accepted.
+#[derive(Debug, Default)]
+pub struct ListenerHandoff {
+    /// The sockets to pass on, and their names.
+    listeners: Vec<(OwnedFd, String)>,
+}
+
+impl ListenerHandoff {
+    /// Creates a handoff without any sockets.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a listening socket to pass on. The socket is duplicated, so the
+    /// listener can still be used, e.g. by passing it to
+    /// [`Server::bind_with`](crate::server::Server::bind_with), until the
+    /// handoff.
+    pub fn with_listener<L>(self, listener: &L) -> io::Result<Self>
+    where
+        L: AsFd,
+    {
+        self.with_named_listener(listener, DEFAULT_LISTENER_NAME)
+    }
+
+    /// Adds a listening socket to pass on under the given name, which the new
+    /// process can look up with [`InheritedListeners::take_named`]. Names
+    /// must not contain colons.
+    pub fn with_named_listener<L>(mut self, listener: &L, name: &str) -> io::Result<Self>
+    where
+        L: AsFd,
+    {
+        if name.contains(':') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "listener names must not contain colons",
+            ));
+        }
+
+        let fd = listener.as_fd().try_clone_to_owned()?;
+        self.listeners.push((fd, name.to_owned()));
+        Ok(self)
+    }
+
+    /// Spawns the new process with the sockets. The sockets are closed in
+    /// this process once the command is dropped.
+    pub fn spawn(self, mut command: Command) -> io::Result<Child> {
+        let mut names = Vec::with_capacity(self.listeners.len());
+        let mut mappings = Vec::with_capacity(self.listeners.len());
+
+        for ((parent_fd, name), child_fd) in self.listeners.into_iter().zip(LISTEN_FDS_START..) {
+            names.push(name);
+            mappings.push(FdMapping {
+                parent_fd,
+                child_fd,
+            });
+        }
+
+        command
+            .env("LISTEN_FDS", mappings.len().to_string())
+            .env("LISTEN_FDNAMES", names.join(":"))
+            .env_remove("LISTEN_PID")
+            .env_remove("LISTEN_FDS_FIRST_FD")
+            .fd_mappings(mappings)
+            .map_err(|_| io::Error::other("conflicting file descriptor mappings"))?;
+
+        command.spawn()
+    }
+}
@@ -1,8 +1,26 @@
 //! Types for extracting request bodies and building response bodies.
 
+use crate::error::{Error, Result};
+use futures_core::Stream;
+use http_body_util::Limited;
+use hyper::body::{Body, Frame, Incoming, SizeHint};
 use std::borrow::{Borrow, BorrowMut};
+use std::fmt::Debug;
+use std::future::Future;
 use std::ops::{Deref, DerefMut};
-use std::sync::Arc;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Sleep;
+
+pub use hyper::body::Bytes;
+
+/// A boxed error produced while streaming a response body.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A boxed stream of response body chunks.
+type BoxStream = Pin<Box<dyn Stream<Item = std::result::Result<Bytes, BoxError>> + Send>>;
 
 /// The HTTP request body as a raw byte slice. This `deref`s to `&[u8]`.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -104,3 +122,255 @@ impl<T> BorrowMut<T> for Json<T> {
         &mut self.0
     }
 }
+
+/// The HTTP request body as an async stream of chunks, for routes that opt in
+/// to streaming through
+/// [`RouteConfig::with_streaming_body`](crate::routing::RouteConfig::with_streaming_body).
+/// The route's maximum body size and body read timeout still apply, and are
+/// reported as errors from the stream. The read timeout starts when the stream
+/// is first polled, not when it is extracted. The stream can only be extracted
+/// once per request.
+pub struct BodyStream {
+    /// The size-limited request body.
+    body: Limited<Incoming>,
+    /// The maximum size of the request body, in bytes.
+    max_body_size: u64,
+    /// The maximum amount of time to spend reading the whole body, if there
+    /// is one.
+    timeout: Option<Duration>,
+    /// The deadline for reading the whole body, set on the first poll.
+    deadline: Option<Pin<Box<Sleep>>>,
+    /// Whether the stream has ended, either normally or with an error.
+    done: bool,
+}
+
+impl BodyStream {
+    /// Wraps a request body, enforcing the given limits.
+    pub(crate) fn new(body: Incoming, max_body_size: u64, timeout: Option<Duration>) -> Self {
+        Self {
+            body: Limited::new(body, usize::try_from(max_body_size).unwrap_or(usize::MAX)),
+            max_body_size,
+            timeout,
+            deadline: None,
+            done: false,
+        }
+    }
+
+    /// Receives the next chunk of the body, or `None` once the whole body has
+    /// been received.
+    pub async fn next_chunk(&mut self) -> Option<Result<Bytes>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl Stream for BodyStream {
+    type Item = Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        if let Some(timeout) = this.timeout.take() {
+            this.deadline = Some(Box::pin(tokio::time::sleep(timeout)));
+        }
+
+        if let Some(deadline) = &mut this.deadline {
+            if deadline.as_mut().poll(cx).is_ready() {
+                this.done = true;
+                return Poll::Ready(Some(Err(Error::RequestTimeout)));
+            }
+        }
+
+        loop {
+            let item = match Pin::new(&mut this.body).poll_frame(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(item) => item,
+            };
+
+            match item {
+                // Trailers are skipped.
+                Some(Ok(frame)) => {
+                    if let Ok(data) = frame.into_data() {
+                        return Poll::Ready(Some(Ok(data)));
+                    }
+                }
+                Some(Err(err)) => {
+                    this.done = true;
+
+                    return Poll::Ready(Some(Err(match err.downcast::<hyper::Error>() {
+                        Ok(err) => Error::ServerError(*err),
+                        // The only other error is a `LengthLimitError`.
+                        Err(_) => Error::PayloadTooLarge(this.max_body_size),
+                    })));
+                }
+                None => {
+                    this.done = true;
+                    return Poll::Ready(None);
+                }
+            }
+        }
+    }
+}
+
+impl Debug for BodyStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BodyStream")
+            .field("max_body_size", &self.max_body_size)
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A response body streamed to the client as it is produced, using chunked
+/// transfer encoding. This wraps any stream of byte chunks. If the stream
+/// produces an error, the response is aborted.
+///
+/// Clones share the same underlying stream, which can only be sent once.
+#[derive(Clone)]
+pub struct StreamBody(Arc<Mutex<Option<BoxStream>>>);
+
+impl StreamBody {
+    /// Creates a response body from a stream of byte chunks.
+    pub fn new<S, B, E>(stream: S) -> Self
+    where
+        S: Stream<Item = std::result::Result<B, E>> + Send + 'static,
+        B: Into<Bytes>,
+        E: Into<BoxError>,
+    {
+        Self(Arc::new(Mutex::new(Some(Box::pin(MapStream(Box::pin(
+            stream,
+        )))))))
+    }
+
+    /// Polls the next chunk of the stream.
+    fn poll_next(
+        &self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<std::result::Result<Bytes, BoxError>>> {
+        let mut stream = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let res = match stream.as_mut() {
+            Some(stream) => stream.as_mut().poll_next(cx),
+            // The stream was already sent through a clone of this body.
+            None => return Poll::Ready(None),
+        };
+
+        if let Poll::Ready(None) = res {
+            *stream = None;
+        }
+
+        res
+    }
+}
+
+impl PartialEq for StreamBody {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for StreamBody {}
+
+impl Debug for StreamBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamBody").finish_non_exhaustive()
+    }
+}
+
+/// Adapts a stream of byte chunks into the stream type used by
+/// [`StreamBody`].
+struct MapStream<S>(Pin<Box<S>>);
+
+impl<S, B, E> Stream for MapStream<S>
+where
+    S: Stream<Item = std::result::Result<B, E>>,
+    B: Into<Bytes>,
+    E: Into<BoxError>,
+{
+    type Item = std::result::Result<Bytes, BoxError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0
+            .as_mut()
+            .poll_next(cx)
+            .map(|item| item.map(|res| res.map(Into::into).map_err(Into::into)))
+    }
+}
+
+/// The body of an HTTP response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResponseBody {
+    /// A body sent all at once.
+    Bytes(Bytes),
+    /// A body streamed to the client as it is produced.
+    Stream(StreamBody),
+}
+
+impl ResponseBody {
+    /// Gets the body as a byte slice, if it is not streamed.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::Bytes(bytes) => Some(bytes),
+            Self::Stream(_) => None,
+        }
+    }
+}
+
+impl Default for ResponseBody {
+    fn default() -> Self {
+        Self::Bytes(Bytes::new())
+    }
+}
+
+impl From<Bytes> for ResponseBody {
+    fn from(value: Bytes) -> Self {
+        Self::Bytes(value)
+    }
+}
+
+impl From<String> for ResponseBody {
+    fn from(value: String) -> Self {
+        Self::Bytes(value.into())
+    }
+}
+
+impl From<StreamBody> for ResponseBody {
+    fn from(value: StreamBody) -> Self {
+        Self::Stream(value)
+    }
+}
+
+impl Body for ResponseBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<std::result::Result<Frame<Self::Data>, Self::Error>>> {
+        match self.get_mut() {
+            Self::Bytes(bytes) if bytes.is_empty() => Poll::Ready(None),
+            Self::Bytes(bytes) => Poll::Ready(Some(Ok(Frame::data(std::mem::take(bytes))))),
+            Self::Stream(stream) => stream
+                .poll_next(cx)
+                .map(|item| item.map(|res| res.map(Frame::data))),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self {
+            Self::Bytes(bytes) => bytes.is_empty(),
+            Self::Stream(_) => false,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self {
+            Self::Bytes(bytes) => SizeHint::with_exact(bytes.len() as u64),
+            Self::Stream(_) => SizeHint::default(),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Per-connection information.
+
+use crate::forwarded::TrustedProxies;
+use crate::http::Version;
+use crate::listener::{Addr, PeerInfo};
+use crate::proxy_protocol::ProxyAddrs;
+use std::borrow::Borrow;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+#[cfg(unix)]
+use tokio::net::unix::UCred;
+
+/// The ID of the next connection accepted by any server in this process.
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Facts about the connection a request arrived on, such as the client's TLS
+/// certificate. These are gathered once when the connection is established
+/// and shared by every request made on it.
+#[derive(Debug, Clone)]
+pub(crate) struct ConnectionState {
+    /// Information about the connection.
+    peer: Arc<PeerInfo>,
+    /// The proxies whose forwarding headers are trusted.
+    trusted_proxies: Arc<TrustedProxies>,
+    /// The ID of the connection, unique within this process.
+    id: u64,
+    /// The number of requests received on the connection so far.
+    requests: Arc<AtomicU64>,
+    /// The position of the current request on the connection, starting at 1,
+    /// or 0 outside of a request.
+    request_count: u64,
+}
+
+impl ConnectionState {
+    /// Creates the state of a newly accepted connection, assigning it an ID.
+    pub fn new(peer: PeerInfo, trusted_proxies: Arc<TrustedProxies>) -> Self {
+        Self {
+            peer: Arc::new(peer),
+            trusted_proxies,
+            id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+            requests: Arc::new(AtomicU64::new(0)),
+            request_count: 0,
+        }
+    }
+
+    /// Counts a new request on the connection, returning the state to hand to
+    /// that request.
+    pub fn next_request(&self) -> Self {
+        Self {
+            request_count: self.requests.fetch_add(1, Ordering::Relaxed) + 1,
+            ..self.clone()
+        }
+    }
+
+    /// Gets the ID of the connection.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Gets the proxies whose forwarding headers are trusted.
+    pub fn trusted_proxies(&self) -> &TrustedProxies {
+        &self.trusted_proxies
+    }
+
+    /// Gets information about the connection as seen by a request made using
+    /// the given protocol version.
+    pub fn connect_info(&self, protocol: Version) -> ConnectInfo {
+        ConnectInfo {
+            remote_addr: self.peer.remote_addr().clone(),
+            local_addr: self.peer.local_addr().clone(),
+            connection_id: self.id,
+            request_count: self.request_count,
+            protocol,
+            proxy: self.peer.get::<ProxyAddrs>().cloned(),
+        }
+    }
+}
+
+impl Deref for ConnectionState {
+    type Target = PeerInfo;
+
+    fn deref(&self) -> &Self::Target {
+        &self.peer
+    }
+}
+
+impl Borrow<PeerInfo> for ConnectionState {
+    fn borrow(&self) -> &PeerInfo {
+        &self.peer
+    }
+}
+
+/// An extractor for information about the connection a request arrived on,
+/// e.g. for logging, rate limiting or abuse investigations.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ConnectInfo {
+    /// The address of the client. Behind a proxy, this is the address of the
+    /// proxy, unless the proxy reported the original address using the PROXY
+    /// protocol.
+    pub remote_addr: Addr,
+    /// The local address the connection was accepted on, or the address the
+    /// original client connected to if reported using the PROXY protocol.
+    pub local_addr: Addr,
+    /// The ID of the connection. IDs are unique within the process, and are
+    /// shared by every request made on the same connection.
+    pub connection_id: u64,
+    /// The position of this request on the connection, starting at 1. With
+    /// HTTP/2, requests are counted in the order they arrive.
+    pub request_count: u64,
+    /// The HTTP protocol version negotiated for the connection.
+    pub protocol: Version,
+    /// The addresses of the proxy's own connection, if the addresses above
+    /// were reported by a proxy using the PROXY protocol.
+    pub proxy: Option<ProxyAddrs>,
+}
+
+/// An extractor for the credentials of the process on the other end of a Unix
+/// domain socket connection. This is only available on servers started with
+/// [`Server::serve_unix`](crate::server::Server::serve_unix) or
+/// [`Server::serve_unix_with`](crate::server::Server::serve_unix_with).
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PeerCredentials {
+    /// The user ID of the peer process.
+    pub uid: u32,
+    /// The group ID of the peer process.
+    pub gid: u32,
+    /// The process ID of the peer process, if the platform reports it.
+    pub pid: Option<i32>,
+}
+
+#[cfg(unix)]
+impl From<UCred> for PeerCredentials {
+    fn from(value: UCred) -> Self {
+        Self {
+            uid: value.uid(),
+            gid: value.gid(),
+            pid: value.pid(),
+        }
+    }
+}
@@ -4,6 +4,7 @@ use crate::http::{Method, StatusCode};
 use crate::response::{ErrorBody, Response};
 use std::collections::HashSet;
 use std::str::Utf8Error;
+use std::time::Duration;
 use thiserror::Error;
 
 /// Notes whether it was the client or the server that caused an error. This is
@@ -86,6 +87,61 @@ pub enum Error {
     /// server side.
     #[error("server json error: {0}")]
     ServerJsonError(serde_json::Error),
+    /// A client certificate was requested, but the client did not present
+    /// one.
+    #[error("no client certificate was presented")]
+    MissingClientCertificate,
+    /// Peer credentials were requested on a connection that does not provide
+    /// them, such as a TCP connection.
+    #[error("peer credentials are not available on this connection")]
+    MissingPeerCredentials,
+    /// The request body is larger than the maximum size allowed for the route.
+    #[error("the request body exceeds the maximum size of {0} bytes")]
+    PayloadTooLarge(u64),
+    /// The request header section is larger than the maximum size allowed
+    /// by the server.
+    #[error("the request header section exceeds the maximum size of {0} bytes")]
+    HeaderSectionTooLarge(usize),
+    /// The request body was not received within the time allowed for the
+    /// route.
+    #[error("the request body was not received in time")]
+    RequestTimeout,
+    /// The route handler did not produce a response within the time allowed
+    /// for the route.
+    #[error("the request could not be handled in time")]
+    HandlerTimeout,
+    /// A `BodyStream` was requested on a route that does not stream request
+    /// bodies, or was requested more than once.
+    #[error("the request body is not available as a stream")]
+    BodyStreamUnavailable,
+    /// A CIDR block could not be parsed.
+    #[error("invalid CIDR block: '{0}'")]
+    InvalidCidr(String),
+    /// The IP address of the client could not be determined.
+    #[error("the client IP address is not known")]
+    ClientIpUnavailable,
+    /// The server is already handling the maximum number of concurrent
+    /// requests. The response tells the client to retry after the given
+    /// amount of time.
+    #[error("the server is overloaded")]
+    Overloaded(Duration),
+    /// A listener failed to accept a connection.
+    #[error("failed to accept a connection: {0}")]
+    AcceptError(std::io::Error),
+    /// The request is not a valid WebSocket handshake.
+    #[error("invalid websocket handshake: {0}")]
+    InvalidWebSocketHandshake(&'static str),
+    /// The client requested a WebSocket protocol version other than 13.
+    #[error("unsupported websocket version")]
+    UnsupportedWebSocketVersion,
+    /// The connection the request arrived on cannot be upgraded to another
+    /// protocol.
+    #[error("the connection cannot be upgraded")]
+    UpgradeUnavailable,
+    /// An error occurred on a WebSocket connection.
+    #[cfg(feature = "websocket")]
+    #[error("websocket error: {0}")]
+    WebSocketError(Box<tokio_tungstenite::tungstenite::Error>),
 }
 
 impl Error {
@@ -103,12 +159,28 @@ impl Error {
             | Self::CookieParseError(_, _)
             | Self::NotFound
             | Self::MethodNotAllowed(_)
-            | Self::UnsupportedMediaType => ErrorSource::Client,
+            | Self::UnsupportedMediaType
+            | Self::MissingClientCertificate
+            | Self::PayloadTooLarge(_)
+            | Self::HeaderSectionTooLarge(_)
+            | Self::RequestTimeout
+            | Self::InvalidWebSocketHandshake(_)
+            | Self::UnsupportedWebSocketVersion => ErrorSource::Client,
             Self::ServerError(_)
             | Self::MissingPathParameterError(_)
             | Self::UnknownStateTypeError(_)
             | Self::NoNextFunction
-            | Self::ServerJsonError(_) => ErrorSource::Server,
+            | Self::ServerJsonError(_)
+            | Self::MissingPeerCredentials
+            | Self::HandlerTimeout
+            | Self::BodyStreamUnavailable
+            | Self::UpgradeUnavailable
+            | Self::InvalidCidr(_)
+            | Self::ClientIpUnavailable
+            | Self::Overloaded(_)
+            | Self::AcceptError(_) => ErrorSource::Server,
+            #[cfg(feature = "websocket")]
+            Self::WebSocketError(_) => ErrorSource::Server,
         }
     }
 
@@ -124,15 +196,30 @@ impl Error {
             | Self::PathParameterParseError(_, _)
             | Self::QueryParameterParseError(_, _)
             | Self::HeaderParseError(_, _)
-            | Self::CookieParseError(_, _) => StatusCode::BAD_REQUEST,
+            | Self::CookieParseError(_, _)
+            | Self::InvalidWebSocketHandshake(_) => StatusCode::BAD_REQUEST,
             Self::NotFound => StatusCode::NOT_FOUND,
             Self::MethodNotAllowed(_) => StatusCode::METHOD_NOT_ALLOWED,
             Self::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::MissingClientCertificate => StatusCode::UNAUTHORIZED,
+            Self::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::HeaderSectionTooLarge(_) => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            Self::RequestTimeout => StatusCode::REQUEST_TIMEOUT,
+            Self::HandlerTimeout | Self::Overloaded(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::UnsupportedWebSocketVersion => StatusCode::UPGRADE_REQUIRED,
             Self::ServerError(_)
             | Self::MissingPathParameterError(_)
             | Self::UnknownStateTypeError(_)
             | Self::NoNextFunction
-            | Self::ServerJsonError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            | Self::ServerJsonError(_)
+            | Self::MissingPeerCredentials
+            | Self::BodyStreamUnavailable
+            | Self::UpgradeUnavailable
+            | Self::InvalidCidr(_)
+            | Self::ClientIpUnavailable
+            | Self::AcceptError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            #[cfg(feature = "websocket")]
+            Self::WebSocketError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 
@@ -142,22 +229,34 @@ impl Error {
             .status_code(self.response_status())
             .body_json(ErrorBody::new(self.to_string()));
 
-        let res = if let Self::MethodNotAllowed(allow) = self {
-            res.header(
+        let res = match self {
+            Self::MethodNotAllowed(allow) => res.header(
                 "Allow",
                 &allow
                     .iter()
                     .map(ToString::to_string)
                     .collect::<Vec<_>>()
                     .join(", "),
-            )
-        } else {
-            res
+            ),
+            Self::UnsupportedWebSocketVersion => res.header("Sec-WebSocket-Version", "13"),
+            // Retry-After is given in whole seconds, so round up.
+            Self::Overloaded(retry_after) => res.header(
+                "Retry-After",
+                &(retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0)).to_string(),
+            ),
+            _ => res,
         };
 
         res
     }
 }
 
+#[cfg(feature = "websocket")]
+impl From<tokio_tungstenite::tungstenite::Error> for Error {
+    fn from(value: tokio_tungstenite::tungstenite::Error) -> Self {
+        Self::WebSocketError(Box::new(value))
+    }
+}
+
 /// The crate-level `Result` type alias.
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,129 @@
+//! Structured events describing the activity of a running server.
+
+use crate::error::Error;
+use crate::http::{Method, StatusCode};
+use crate::listener::Addr;
+use crate::routing::RoutePath;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// Information about a request, shared by every event concerning it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestInfo {
+    /// The ID of the connection the request arrived on, as reported by
+    /// [`ServerEvent::ConnectionAccepted`].
+    pub connection_id: u64,
+    /// The request method.
+    pub method: Method,
+    /// The requested path.
+    pub path: String,
+    /// The pattern of the route that matched the request, e.g.
+    /// `/users/{id}`, or `None` if no route matched.
+    pub route: Option<RoutePath>,
+}
+
+/// An event that occurred on a running server.
+#[derive(Debug, Clone)]
+pub enum ServerEvent {
+    /// A connection was accepted.
+    ConnectionAccepted {
+        /// The ID of the connection, unique within the process.
+        connection_id: u64,
+        /// The address of the client.
+        remote_addr: Addr,
+        /// The local address the connection was accepted on.
+        local_addr: Addr,
+    },
+    /// A connection was closed, either by the client, by the server, or
+    /// forcefully during a shutdown.
+    ConnectionClosed {
+        /// The ID of the connection.
+        connection_id: u64,
+        /// How long the connection was open.
+        duration: Duration,
+    },
+    /// A listener failed to accept a connection.
+    AcceptFailed(Arc<Error>),
+    /// A request was received.
+    RequestStarted(RequestInfo),
+    /// A response to a request was produced. The response body may still be
+    /// sending. Every request ends with either this event or
+    /// [`RequestFailed`](Self::RequestFailed).
+    RequestFinished {
+        /// The request.
+        request: RequestInfo,
+        /// The status code of the response.
+        status: StatusCode,
+        /// How long it took to produce the response.
+        latency: Duration,
+    },
+    /// The connection failed before a response to a request was produced,
+    /// e.g. because the client closed it while sending the request body. The
+    /// connection is closed without a response.
+    RequestFailed {
+        /// The request.
+        request: RequestInfo,
+        /// The error.
+        error: Arc<Error>,
+        /// How long the request was handled before failing.
+        latency: Duration,
+    },
+    /// A route handler, or its middleware, responded with an error. Both
+    /// client and server errors are reported.
+    HandlerError {
+        /// The request.
+        request: RequestInfo,
+        /// The error.
+        error: Arc<Error>,
+    },
+}
+
+/// The sending half of an event reporting channel.
+#[derive(Debug, Clone)]
+pub struct EventSender(UnboundedSender<Option<ServerEvent>>);
+
+impl EventSender {
+    /// Sends an event through the event reporting channel.
+    pub fn report(&self, event: ServerEvent) {
+        _ = self.0.send(Some(event));
+    }
+
+    /// Closes the event reporting channel.
+    pub fn close(&self) {
+        _ = self.0.send(None);
+    }
+}
+
+/// The receiving half of an event reporting channel.
+#[derive(Debug)]
+pub enum EventReceiver {
+    /// The server is running, and events can still be received.
+    Active(UnboundedReceiver<Option<ServerEvent>>),
+    /// The server has been closed.
+    Done,
+}
+
+impl EventReceiver {
+    /// Waits to receive the next event from the server.
+    pub async fn next(&mut self) -> Option<ServerEvent> {
+        match self {
+            Self::Active(receiver) => match receiver.recv().await.flatten() {
+                Some(event) => Some(event),
+                None => {
+                    *self = Self::Done;
+                    None
+                }
+            },
+            Self::Done => None,
+        }
+    }
+}
+
+/// Creates an event reporting stream. Note that the underlying channel is
+/// unbounded, so events must be consumed faster than they are produced to
+/// avoid causing the process to run out of memory.
+pub fn event_stream() -> (EventSender, EventReceiver) {
+    let (tx, rx) = unbounded_channel();
+    (EventSender(tx), EventReceiver::Active(rx))
+}
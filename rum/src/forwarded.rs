@@ -0,0 +1,450 @@
+//! Resolution of the original client of requests made through proxies.
+
+use crate::error::{Error, Result};
+use crate::header::HeaderMap;
+use crate::listener::{Addr, PeerInfo};
+use std::borrow::Borrow;
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::ops::Deref;
+use std::str::FromStr;
+
+/// A block of IP addresses in CIDR notation, e.g. `10.0.0.0/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpCidr {
+    /// The first address of the block.
+    addr: IpAddr,
+    /// The number of leading bits shared by every address in the block.
+    prefix_len: u8,
+}
+
+impl IpCidr {
+    /// Creates a block from an address and a prefix length. Bits of the
+    /// address beyond the prefix are ignored, and IPv4-mapped IPv6 blocks
+    /// are converted to IPv4 blocks. This returns `None` if the prefix is
+    /// longer than the address.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Option<Self> {
+        let (addr, prefix_len) = match (addr, addr.to_canonical()) {
+            (IpAddr::V6(_), addr @ IpAddr::V4(_)) if (96..=128).contains(&prefix_len) => {
+                (addr, prefix_len - 96)
+            }
+            _ => (addr, prefix_len),
+        };
+
+        Some(Self {
+            addr: mask(addr, prefix_len)?,
+            prefix_len,
+        })
+    }
+
+    /// Gets the first address of the block.
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// Gets the prefix length of the block.
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Checks whether an address is part of the block. IPv4-mapped IPv6
+    /// addresses are treated as IPv4 addresses.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let addr = addr.to_canonical();
+
+        if mask(addr, self.prefix_len) == Some(self.addr) {
+            return true;
+        }
+
+        // An IPv6 block too wide to be converted to IPv4 may still contain
+        // IPv4-mapped addresses.
+        match addr {
+            IpAddr::V4(addr) => {
+                mask(IpAddr::V6(addr.to_ipv6_mapped()), self.prefix_len) == Some(self.addr)
+            }
+            IpAddr::V6(_) => false,
+        }
+    }
+}
+
+/// Clears the bits of an address beyond a prefix. This returns `None` if the
+/// prefix is longer than the address.
+fn mask(addr: IpAddr, prefix_len: u8) -> Option<IpAddr> {
+    match addr {
+        IpAddr::V4(addr) if prefix_len <= 32 => {
+            let mask = u32::MAX
+                .checked_shl(32 - u32::from(prefix_len))
+                .unwrap_or(0);
+            Some(IpAddr::V4((u32::from(addr) & mask).into()))
+        }
+        IpAddr::V6(addr) if prefix_len <= 128 => {
+            let mask = u128::MAX
+                .checked_shl(128 - u32::from(prefix_len))
+                .unwrap_or(0);
+            Some(IpAddr::V6((u128::from(addr) & mask).into()))
+        }
+        _ => None,
+    }
+}
+
+impl From<IpAddr> for IpCidr {
+    fn from(value: IpAddr) -> Self {
+        let addr = value.to_canonical();
+        let prefix_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+
+        Self { addr, prefix_len }
+    }
+}
+
+impl FromStr for IpCidr {
+    type Err = Error;
+
+    /// Parses a block in CIDR notation. A single address without a prefix
+    /// length is parsed as a block containing only that address.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidCidr(s.to_owned());
+
+        match s.split_once('/') {
+            Some((addr, prefix_len)) => Self::new(
+                addr.parse().map_err(|_| invalid())?,
+                prefix_len.parse().map_err(|_| invalid())?,
+            )
+            .ok_or_else(invalid),
+            None => Ok(Self::from(s.parse::<IpAddr>().map_err(|_| invalid())?)),
+        }
+    }
+}
+
+impl Display for IpCidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// The header trusted proxies use to report the original client of a
+/// request. Only the chosen header is read, since any other header may have
+/// been set by the client and passed through unchanged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ForwardedHeader {
+    /// The `X-Forwarded-For` header, along with the `X-Forwarded-Proto` and
+    /// `X-Forwarded-Host` headers for the origin.
+    #[default]
+    XForwardedFor,
+    /// The standard `Forwarded` header, described in RFC 7239.
+    Forwarded,
+    /// The `X-Real-IP` header, which reports only the client's address.
+    XRealIp,
+}
+
+/// The proxies whose forwarding headers are trusted. Requests from any other
+/// peer are attributed to the peer itself, and their forwarding headers are
+/// ignored, since they can be set by anyone.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies {
+    /// The trusted blocks of addresses.
+    cidrs: Vec<IpCidr>,
+    /// Whether peers on Unix domain sockets and in-memory connections are
+    /// trusted.
+    local: bool,
+    /// The header the proxies report the client with.
+    header: ForwardedHeader,
+}
+
+impl TrustedProxies {
+    /// Creates a configuration that trusts no proxies.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trusts proxies within a block of addresses.
+    pub fn with_cidr(mut self, cidr: IpCidr) -> Self {
+        self.cidrs.push(cidr);
+        self
+    }
+
+    /// Sets whether peers on Unix domain sockets and in-memory connections,
+    /// which have no IP address, are trusted, e.g. when a proxy on the same
+    /// host connects through a Unix domain socket. These are not trusted by
+    /// default.
+    pub fn with_local(mut self, trusted: bool) -> Self {
+        self.local = trusted;
+        self
+    }
+
+    /// Sets the header the proxies report the original client with. This
+    /// must match the header the proxies set, and defaults to
+    /// [`ForwardedHeader::XForwardedFor`].
+    pub fn with_header(mut self, header: ForwardedHeader) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Checks whether an IP address belongs to a trusted proxy.
+    fn is_trusted_ip(&self, ip: IpAddr) -> bool {
+        self.cidrs.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    /// Checks whether a peer address belongs to a trusted proxy.
+    fn is_trusted_addr(&self, addr: &Addr) -> bool {
+        match addr {
+            Addr::Tcp(addr) => self.is_trusted_ip(addr.ip()),
+            Addr::Unix(_) | Addr::Memory => self.local,
+            Addr::Other(_) => false,
+        }
+    }
+}
+
+impl FromIterator<IpCidr> for TrustedProxies {
+    fn from_iter<T: IntoIterator<Item = IpCidr>>(iter: T) -> Self {
+        Self {
+            cidrs: iter.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Parses a node of a forwarding header, e.g. `192.0.2.60`,
+/// `192.0.2.60:8080`, `[2001:db8::17]:4711` or `2001:db8::17`. Obfuscated and
+/// unknown nodes yield `None`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+
+    node.parse()
+        .or_else(|_| node.split_once(':').ok_or(())?.0.parse().map_err(|_| ()))
+        .ok()
+}
+
+/// Gets every value of a header as one comma-separated list.
+fn header_list<'a>(headers: &'a HeaderMap, name: &str) -> Vec<&'a str> {
+    headers
+        .get_optional(name)
+        .unwrap_or_default()
+        .iter()
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// An element of a forwarding header, added by one proxy to describe the
+/// request it received.
+#[derive(Debug, Clone, Copy, Default)]
+struct Hop<'a> {
+    /// The address the request was received from, if it could be parsed.
+    node: Option<IpAddr>,
+    /// The scheme the request was received with, if reported.
+    proto: Option<&'a str>,
+    /// The host the request was received for, if reported.
+    host: Option<&'a str>,
+}
+
+/// Gets a parameter of an element of the `Forwarded` header.
+fn forwarded_param<'a>(element: &'a str, param: &str) -> Option<&'a str> {
+    element.split(';').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case(param)
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
+
+/// Gets the value of a list header that lines up with the element of
+/// `X-Forwarded-For` at the given distance from the end. Proxies that set the
+/// header instead of appending to it leave fewer values, in which case the
+/// first one is used.
+fn aligned_value<'a>(values: &[&'a str], from_end: usize) -> Option<&'a str> {
+    let last = values.len().checked_sub(1)?;
+    values.get(last - from_end.min(last)).copied()
+}
+
+/// Gets the hops reported by the chosen forwarding header, in the order they
+/// were forwarded.
+fn forwarded_hops(headers: &HeaderMap, header: ForwardedHeader) -> Vec<Hop<'_>> {
+    match header {
+        ForwardedHeader::Forwarded => header_list(headers, "Forwarded")
+            .into_iter()
+            .map(|element| Hop {
+                node: forwarded_param(element, "for").and_then(parse_node),
+                proto: forwarded_param(element, "proto"),
+                host: forwarded_param(element, "host"),
+            })
+            .collect(),
+        ForwardedHeader::XForwardedFor => {
+            let nodes = header_list(headers, "X-Forwarded-For");
+            let protos = header_list(headers, "X-Forwarded-Proto");
+            let hosts = header_list(headers, "X-Forwarded-Host");
+
+            nodes
+                .iter()
+                .enumerate()
+                .map(|(index, node)| {
+                    let from_end = nodes.len() - 1 - index;
+
+                    Hop {
+                        node: parse_node(node),
+                        proto: aligned_value(&protos, from_end),
+                        host: aligned_value(&hosts, from_end),
+                    }
+                })
+                .collect()
+        }
+        ForwardedHeader::XRealIp => header_list(headers, "X-Real-IP")
+            .last()
+            .map(|node| {
+                vec![Hop {
+                    node: parse_node(node),
+                    ..Hop::default()
+                }]
+            })
+            .unwrap_or_default(),
+    }
+}
+
+/// Follows the forwarding header back through every trusted proxy. This
+/// returns the address of the client, which is the first untrusted address,
+/// along with the hop added by the outermost trusted proxy, which describes
+/// the request as the client made it. Hops before that one were added by the
+/// client, and are not trusted.
+fn resolve<'a>(
+    peer: &PeerInfo,
+    trusted_proxies: &TrustedProxies,
+    headers: &'a HeaderMap,
+) -> (Option<IpAddr>, Option<Hop<'a>>) {
+    let mut client = match peer.remote_addr() {
+        Addr::Tcp(addr) => Some(addr.ip().to_canonical()),
+        _ => None,
+    };
+    let mut edge = None;
+
+    if trusted_proxies.is_trusted_addr(peer.remote_addr()) {
+        for hop in forwarded_hops(headers, trusted_proxies.header)
+            .into_iter()
+            .rev()
+        {
+            edge = Some(hop);
+
+            // An address that cannot be parsed cannot be followed further, so
+            // the proxy that reported it is the best known client.
+            let Some(ip) = hop.node else {
+                break;
+            };
+
+            client = Some(ip.to_canonical());
+
+            if !trusted_proxies.is_trusted_ip(ip) {
+                break;
+            }
+        }
+    }
+
+    (client, edge)
+}
+
+/// Resolves the address of the client that originally made a request. If the
+/// peer is a trusted proxy, the forwarding header is followed back through
+/// every trusted proxy, and the first untrusted address is the client.
+pub(crate) fn client_ip(
+    peer: &PeerInfo,
+    trusted_proxies: &TrustedProxies,
+    headers: &HeaderMap,
+) -> Result<IpAddr> {
+    resolve(peer, trusted_proxies, headers)
+        .0
+        .ok_or(Error::ClientIpUnavailable)
+}
+
+/// Resolves the scheme and host of the URL the client originally requested.
+/// These are taken from the hop added by the outermost trusted proxy.
+pub(crate) fn origin(
+    peer: &PeerInfo,
+    trusted_proxies: &TrustedProxies,
+    headers: &HeaderMap,
+    scheme: Option<&str>,
+    authority: Option<&str>,
+) -> RequestOrigin {
+    let edge = resolve(peer, trusted_proxies, headers)
+        .1
+        .unwrap_or_default();
+
+    #[cfg(feature = "tls")]
+    let secure = peer.get::<crate::tls::TlsConnection>().is_some();
+    #[cfg(not(feature = "tls"))]
+    let secure = false;
+
+    let scheme = edge
+        .proto
+        .or(scheme)
+        .unwrap_or(if secure { "https" } else { "http" })
+        .to_ascii_lowercase();
+    let host = edge
+        .host
+        .or_else(|| authority.or_else(|| header_list(headers, "Host").first().copied()))
+        .map(ToOwned::to_owned);
+
+    RequestOrigin { scheme, host }
+}
+
+/// An extractor for the IP address of the client that originally made the
+/// request. If the peer is one of the
+/// [trusted proxies](crate::server::Server::with_trusted_proxies), this is
+/// resolved from the [header](TrustedProxies::with_header) the proxies are
+/// configured to use. Otherwise, it is the address of the peer.
+///
+/// Extraction fails if no IP address is known, e.g. for requests on Unix
+/// domain sockets without forwarding headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ClientIp(pub(crate) IpAddr);
+
+impl ClientIp {
+    /// Moves the address out of this wrapper.
+    pub fn into_inner(self) -> IpAddr {
+        self.0
+    }
+}
+
+impl Deref for ClientIp {
+    type Target = IpAddr;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Borrow<IpAddr> for ClientIp {
+    fn borrow(&self) -> &IpAddr {
+        &self.0
+    }
+}
+
+/// An extractor for the scheme and host of the URL the client originally
+/// requested, for building absolute URLs. If the peer is one of the
+/// [trusted proxies](crate::server::Server::with_trusted_proxies), these are
+/// resolved from the `Forwarded` header, or the `X-Forwarded-Proto` and
+/// `X-Forwarded-Host` headers, depending on the
+/// [header](TrustedProxies::with_header) the proxies are configured to use,
+/// as reported by the outermost trusted proxy. Otherwise, or if that proxy
+/// did not report them, they are taken from the request itself.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestOrigin {
+    /// The scheme, e.g. `https`.
+    pub scheme: String,
+    /// The host, possibly including a port, if known.
+    pub host: Option<String>,
+}
+
+impl RequestOrigin {
+    /// Gets the base URL of the origin, e.g. `https://example.com`, if the
+    /// host is known.
+    pub fn base_url(&self) -> Option<String> {
+        self.host
+            .as_ref()
+            .map(|host| format!("{}://{}", self.scheme, host))
+    }
+}
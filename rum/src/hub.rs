@@ -0,0 +1,280 @@
+//! In-process publish/subscribe for realtime fan-out.
+
+use futures_core::Stream;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::task::{Context, Poll};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{self, Receiver, Sender};
+
+/// The default number of messages each channel retains for subscribers that
+/// have not yet received them.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 64;
+
+/// The channels of a hub, by name.
+type Channels<T> = Mutex<HashMap<String, Sender<T>>>;
+
+/// A pending receive on a channel, which hands the receiver back once it
+/// completes.
+type RecvFuture<T> = Pin<Box<dyn Future<Output = (Result<T, RecvError>, Receiver<T>)> + Send>>;
+
+/// What happens to a subscriber that falls so far behind that messages it has
+/// not received are no longer retained.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum LagPolicy {
+    /// The missed messages are skipped, and the subscriber continues with the
+    /// oldest message still retained. The number of skipped messages is
+    /// available through [`Subscription::missed`].
+    #[default]
+    Skip,
+    /// The subscription ends, e.g. so that the client reconnects and resyncs.
+    Disconnect,
+}
+
+/// A hub of named broadcast channels, used to fan messages out to every
+/// subscriber of a channel, e.g. to push live updates to clients. Everything
+/// happens in-process. Channels are created when first subscribed to, and
+/// removed once their last subscriber is gone, so messages published to a
+/// channel without subscribers are dropped.
+///
+/// Clones share the same channels, so a hub can be registered with
+/// [`Server::with_state`](crate::server::Server::with_state) and extracted
+/// in handlers with [`State`](crate::state::State).
+pub struct Hub<T> {
+    /// The channels, by name.
+    channels: Arc<Channels<T>>,
+    /// The number of messages each channel retains.
+    capacity: usize,
+    /// What happens to subscribers that fall behind.
+    lag_policy: LagPolicy,
+}
+
+impl<T> Hub<T>
+where
+    T: Clone + Send + 'static,
+{
+    /// Creates a new hub with no channels.
+    pub fn new() -> Self {
+        Self {
+            channels: Arc::new(Mutex::new(HashMap::new())),
+            capacity: DEFAULT_CHANNEL_CAPACITY,
+            lag_policy: LagPolicy::default(),
+        }
+    }
+
+    /// Sets the number of messages each channel retains for subscribers that
+    /// have not yet received them. This only affects channels created
+    /// afterwards. The default is [`DEFAULT_CHANNEL_CAPACITY`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the capacity is zero.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "channel capacity must be greater than zero");
+        self.capacity = capacity;
+        self
+    }
+
+    /// Sets what happens to subscribers that fall behind. This only affects
+    /// subscriptions created afterwards. The default is [`LagPolicy::Skip`].
+    pub fn with_lag_policy(mut self, lag_policy: LagPolicy) -> Self {
+        self.lag_policy = lag_policy;
+        self
+    }
+
+    /// Locks the channels.
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Sender<T>>> {
+        self.channels.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Subscribes to a channel, creating it if needed. The subscription
+    /// receives every message published to the channel from now on.
+    pub fn subscribe(&self, channel: &str) -> Subscription<T> {
+        let receiver = self
+            .lock()
+            .entry(channel.to_owned())
+            .or_insert_with(|| broadcast::channel(self.capacity).0)
+            .subscribe();
+
+        Subscription {
+            channel: channel.to_owned(),
+            channels: Arc::downgrade(&self.channels),
+            recv: Some(Box::pin(recv(receiver))),
+            lag_policy: self.lag_policy,
+            missed: 0,
+        }
+    }
+
+    /// Publishes a message to every subscriber of a channel, returning the
+    /// number of subscribers it was sent to.
+    pub fn publish(&self, channel: &str, message: T) -> usize {
+        self.lock()
+            .get(channel)
+            .and_then(|sender| sender.send(message).ok())
+            .unwrap_or(0)
+    }
+
+    /// Gets the number of subscribers of a channel.
+    pub fn presence(&self, channel: &str) -> usize {
+        self.lock()
+            .get(channel)
+            .map(Sender::receiver_count)
+            .unwrap_or(0)
+    }
+
+    /// Gets the names of every channel that has subscribers.
+    pub fn channels(&self) -> Vec<String> {
+        self.lock().keys().cloned().collect()
+    }
+}
+
+impl<T> Clone for Hub<T> {
+    fn clone(&self) -> Self {
+        Self {
+            channels: Arc::clone(&self.channels),
+            capacity: self.capacity,
+            lag_policy: self.lag_policy,
+        }
+    }
+}
+
+impl<T> Default for Hub<T>
+where
+    T: Clone + Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Debug for Hub<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hub")
+            .field("capacity", &self.capacity)
+            .field("lag_policy", &self.lag_policy)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Receives the next message on a channel.
+async fn recv<T>(mut receiver: Receiver<T>) -> (Result<T, RecvError>, Receiver<T>)
+where
+    T: Clone,
+{
+    let res = receiver.recv().await;
+    (res, receiver)
+}
+
+/// A subscription to a channel of a [`Hub`]. Messages are received through
+/// [`recv`](Self::recv) or the [`Stream`] implementation, which can be turned
+/// into a response, e.g. with [`Sse`](crate::sse::Sse). Dropping the
+/// subscription unsubscribes from the channel.
+pub struct Subscription<T>
+where
+    T: Clone + Send + 'static,
+{
+    /// The name of the channel.
+    channel: String,
+    /// The channels of the hub.
+    channels: Weak<Channels<T>>,
+    /// The pending receive, or `None` once the subscription has ended.
+    recv: Option<RecvFuture<T>>,
+    /// What happens when the subscription falls behind.
+    lag_policy: LagPolicy,
+    /// The number of messages skipped so far.
+    missed: u64,
+}
+
+impl<T> Subscription<T>
+where
+    T: Clone + Send + 'static,
+{
+    /// Gets the name of the channel.
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    /// Gets the number of messages skipped so far because the subscription
+    /// fell behind.
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+
+    /// Receives the next message. This returns `None` once the subscription
+    /// has ended.
+    pub async fn recv(&mut self) -> Option<T> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Removes the channel from the hub if it no longer has subscribers.
+    fn remove_if_unused(&self) {
+        if let Some(channels) = self.channels.upgrade() {
+            let mut channels = channels.lock().unwrap_or_else(PoisonError::into_inner);
+
+            if channels
+                .get(&self.channel)
+                .is_some_and(|sender| sender.receiver_count() == 0)
+            {
+                channels.remove(&self.channel);
+            }
+        }
+    }
+}
+
+impl<T> Stream for Subscription<T>
+where
+    T: Clone + Send + 'static,
+{
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let Some(recv) = self.recv.as_mut() else {
+                return Poll::Ready(None);
+            };
+            let (res, receiver) = std::task::ready!(recv.as_mut().poll(cx));
+
+            match res {
+                Ok(message) => {
+                    self.recv = Some(Box::pin(self::recv(receiver)));
+                    return Poll::Ready(Some(message));
+                }
+                Err(RecvError::Lagged(missed)) if self.lag_policy == LagPolicy::Skip => {
+                    self.missed += missed;
+                    self.recv = Some(Box::pin(self::recv(receiver)));
+                }
+                Err(_) => {
+                    drop(receiver);
+                    self.recv = None;
+                    self.remove_if_unused();
+                }
+            }
+        }
+    }
+}
+
+impl<T> Drop for Subscription<T>
+where
+    T: Clone + Send + 'static,
+{
+    fn drop(&mut self) {
+        self.recv = None;
+        self.remove_if_unused();
+    }
+}
+
+impl<T> Debug for Subscription<T>
+where
+    T: Clone + Send + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Subscription")
+            .field("channel", &self.channel)
+            .field("lag_policy", &self.lag_policy)
+            .field("missed", &self.missed)
+            .finish_non_exhaustive()
+    }
+}
@@ -2,60 +2,97 @@
 //!
 //! A high-level web framework that emphasizes simplicity.
 
-#![forbid(unsafe_code)]
+#![deny(unsafe_code)]
 #![warn(missing_docs)]
 #![warn(clippy::missing_docs_in_private_items)]
 #![allow(incomplete_features)]
 #![cfg_attr(feature = "nightly", feature(adt_const_params))]
+#![cfg_attr(feature = "nightly", feature(unsized_const_params))]
 #![cfg_attr(feature = "nightly", feature(fn_traits))]
 #![cfg_attr(feature = "nightly", feature(unboxed_closures))]
 
+#[cfg(all(unix, feature = "socket-activation"))]
+pub mod activation;
 pub mod body;
+pub mod connection;
 pub mod cookie;
 pub mod error;
+pub mod event;
+pub mod forwarded;
 pub mod header;
+pub mod hub;
+pub mod listener;
 #[macro_use]
 pub(crate) mod macros;
 pub mod middleware;
 pub mod path;
+pub mod proxy_protocol;
 pub mod query;
 pub mod request;
 pub mod response;
 pub mod routing;
 pub mod server;
+pub mod signal;
+pub mod sse;
 pub mod state;
+#[cfg(feature = "tls")]
+pub mod tls;
 pub(crate) mod typemap;
+pub mod upgrade;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
 /// General HTTP-related types.
 pub mod http {
-    pub use http::{Method, StatusCode};
+    pub use http::{Method, StatusCode, Version};
 }
 
 /// The crate prelude. This contains the most useful functions and types from
 /// the crate.
 pub mod prelude {
-    pub use crate::body::{BodyRaw, BodyString, Json};
+    #[cfg(all(unix, feature = "socket-activation"))]
+    pub use crate::activation::{InheritedListener, InheritedListeners, ListenerHandoff};
+    pub use crate::body::{BodyRaw, BodyStream, BodyString, Bytes, Json, ResponseBody, StreamBody};
+    pub use crate::connection::ConnectInfo;
+    #[cfg(unix)]
+    pub use crate::connection::PeerCredentials;
     #[cfg(feature = "nightly")]
     pub use crate::cookie::{Cookie, CookieOptional};
     pub use crate::cookie::{CookieMap, Cookies, SetCookie};
+    pub use crate::event::{event_stream, EventReceiver, EventSender, RequestInfo, ServerEvent};
+    pub use crate::forwarded::{ClientIp, ForwardedHeader, IpCidr, RequestOrigin, TrustedProxies};
     #[cfg(feature = "nightly")]
     pub use crate::header::{Header, HeaderOptional};
     pub use crate::header::{HeaderMap, Headers};
-    pub use crate::http::{Method, StatusCode};
+    pub use crate::http::{Method, StatusCode, Version};
+    pub use crate::hub::{Hub, LagPolicy, Subscription};
+    pub use crate::listener::{memory_listener, Addr, Listener, PeerInfo, SocketListener};
     pub use crate::middleware::{Middleware, NextFn};
     #[cfg(feature = "nightly")]
     pub use crate::path::PathParam;
     pub use crate::path::{PathParamMap, PathParams};
+    pub use crate::proxy_protocol::{ProxyAddrs, ProxyProtocolListener, ProxyProtocolMode};
     #[cfg(feature = "nightly")]
     pub use crate::query::{QueryParam, QueryParamBool, QueryParamOptional};
     pub use crate::query::{QueryParamMap, QueryParams};
     pub use crate::request::{FromRequest, Request};
     pub use crate::response::{IntoResponse, Response};
-    pub use crate::routing::{RouteGroup, RoutePath, RoutePathMatched, RoutePathString};
+    pub use crate::routing::{
+        RouteConfig, RouteGroup, RouteHandler, RoutePath, RoutePathMatched, RoutePathString,
+    };
+    #[cfg(feature = "http2")]
+    pub use crate::server::Http2Config;
     pub use crate::server::{
-        error_report_stream, shutdown_signal, ErrorReceiver, ErrorSender, Server, ShutdownReceiver,
-        ShutdownSender,
+        error_report_stream, shutdown_signal, ErrorReceiver, ErrorSender, RunningServer, Server,
+        ServerConfig, ShutdownReceiver, ShutdownReport, ShutdownSender, SocketConfig,
     };
-    pub use crate::state::{LocalState, State};
+    pub use crate::signal::Signals;
+    pub use crate::sse::{Event, LastEventId, Sse};
+    pub use crate::state::{LocalState, ServerState, State};
+    #[cfg(feature = "tls")]
+    pub use crate::tls::{ClientAuth, ClientCertificate, TlsConfig, TlsListener};
+    pub use crate::upgrade::{OnUpgrade, Upgraded};
+    #[cfg(feature = "websocket")]
+    pub use crate::websocket::{WebSocket, WebSocketUpgrade};
     pub use rum_macros::{handler, middleware};
 }
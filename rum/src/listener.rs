@@ -0,0 +1,273 @@
+//! Connection listener types.
+
+#[cfg(unix)]
+use crate::connection::PeerCredentials;
+use crate::server::SocketConfig;
+use crate::typemap::TypeMap;
+use std::fmt::Display;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use tokio::io::{duplex, AsyncRead, AsyncWrite, DuplexStream};
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// The buffer size of each direction of an in-memory connection.
+const MEMORY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The address of one end of a connection.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Addr {
+    /// A TCP socket address.
+    Tcp(SocketAddr),
+    /// A Unix domain socket address. Unnamed sockets, such as those of most
+    /// clients, have no path.
+    Unix(Option<PathBuf>),
+    /// An in-memory connection created by a [`MemoryConnector`].
+    Memory,
+    /// An address of a custom transport, described as a string.
+    Other(String),
+}
+
+impl Display for Addr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp(addr) => addr.fmt(f),
+            Self::Unix(Some(path)) => write!(f, "unix:{}", path.display()),
+            Self::Unix(None) => f.write_str("unix:(unnamed)"),
+            Self::Memory => f.write_str("memory"),
+            Self::Other(addr) => f.write_str(addr),
+        }
+    }
+}
+
+impl From<SocketAddr> for Addr {
+    fn from(value: SocketAddr) -> Self {
+        Self::Tcp(value)
+    }
+}
+
+/// Information about an accepted connection. Besides the addresses of both
+/// ends, this can carry arbitrary facts about the connection, such as a TLS
+/// client certificate, which are made available to every request on the
+/// connection through [`RequestInner::peer_info`](crate::request::RequestInner::peer_info).
+#[derive(Debug)]
+pub struct PeerInfo {
+    /// The address of the remote end of the connection.
+    remote_addr: Addr,
+    /// The address of the local end of the connection.
+    local_addr: Addr,
+    /// Additional facts about the connection.
+    extensions: TypeMap,
+}
+
+impl PeerInfo {
+    /// Creates connection information from the addresses of both ends.
+    pub fn new(remote_addr: Addr, local_addr: Addr) -> Self {
+        Self {
+            remote_addr,
+            local_addr,
+            extensions: TypeMap::new(),
+        }
+    }
+
+    /// Gets the address of the remote end of the connection.
+    pub fn remote_addr(&self) -> &Addr {
+        &self.remote_addr
+    }
+
+    /// Gets the address of the local end of the connection.
+    pub fn local_addr(&self) -> &Addr {
+        &self.local_addr
+    }
+
+    /// Replaces the address of the remote end of the connection, e.g. when a
+    /// wrapping listener learns the original client address.
+    pub fn set_remote_addr(&mut self, remote_addr: Addr) {
+        self.remote_addr = remote_addr;
+    }
+
+    /// Replaces the address of the local end of the connection.
+    pub fn set_local_addr(&mut self, local_addr: Addr) {
+        self.local_addr = local_addr;
+    }
+
+    /// Attaches a fact about the connection. Only one value of each type can
+    /// be stored; if a value of the same type is already present, it is
+    /// replaced and returned.
+    pub fn insert<T>(&mut self, value: T) -> Option<T>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.extensions.insert(value)
+    }
+
+    /// Gets a fact about the connection.
+    pub fn get<T>(&self) -> Option<&T>
+    where
+        T: 'static,
+    {
+        self.extensions.get()
+    }
+}
+
+/// A source of incoming connections for the server. Implementations exist for
+/// TCP listeners, Unix domain socket listeners and in-memory connections
+/// (see [`memory_listener`]), and listeners can wrap one another to perform
+/// work on each connection before HTTP is served on it, as
+/// [`TlsListener`](crate::tls::TlsListener) does.
+pub trait Listener: Send + 'static {
+    /// The IO stream of an accepted connection.
+    type Io: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    /// Waits for the next connection. This must be cancellation safe, as the
+    /// server stops waiting for connections when it shuts down. Errors of
+    /// kind `ConnectionAborted`, `ConnectionReset` or `ConnectionRefused` are
+    /// treated as affecting only a single connection, and the server keeps
+    /// accepting right away. Any other error, such as running out of file
+    /// descriptors, is reported, and the server backs off briefly before
+    /// accepting again.
+    fn accept(&mut self) -> impl Future<Output = io::Result<(Self::Io, PeerInfo)>> + Send;
+
+    /// Gets the local address the listener is bound to.
+    fn local_addr(&self) -> io::Result<Addr>;
+}
+
+impl Listener for TcpListener {
+    type Io = tokio::net::TcpStream;
+
+    async fn accept(&mut self) -> io::Result<(Self::Io, PeerInfo)> {
+        let (conn, remote_addr) = TcpListener::accept(self).await?;
+        let local_addr = conn.local_addr()?;
+
+        Ok((conn, PeerInfo::new(remote_addr.into(), local_addr.into())))
+    }
+
+    fn local_addr(&self) -> io::Result<Addr> {
+        TcpListener::local_addr(self).map(Addr::Tcp)
+    }
+}
+
+/// A TCP listener that applies a [`SocketConfig`] to the listening socket and
+/// to every connection it accepts.
+#[derive(Debug)]
+pub struct SocketListener {
+    /// The underlying listener.
+    inner: TcpListener,
+    /// The socket settings.
+    config: SocketConfig,
+}
+
+impl SocketListener {
+    /// Binds a listener to the given address using the given settings. If
+    /// the address resolves to multiple addresses, each is tried in turn
+    /// until one succeeds.
+    pub async fn bind<A>(addr: A, config: SocketConfig) -> io::Result<Self>
+    where
+        A: ToSocketAddrs,
+    {
+        let mut listeners = config.bind_all(addr, 1).await?;
+        Self::from_std(listeners.remove(0), config)
+    }
+
+    /// Wraps a bound listener. This must be called within a Tokio runtime,
+    /// whose IO driver the listener is registered with.
+    pub(crate) fn from_std(
+        listener: std::net::TcpListener,
+        config: SocketConfig,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            inner: TcpListener::from_std(listener)?,
+            config,
+        })
+    }
+}
+
+impl Listener for SocketListener {
+    type Io = tokio::net::TcpStream;
+
+    async fn accept(&mut self) -> io::Result<(Self::Io, PeerInfo)> {
+        let (conn, peer) = Listener::accept(&mut self.inner).await?;
+        self.config.apply(&conn)?;
+        Ok((conn, peer))
+    }
+
+    fn local_addr(&self) -> io::Result<Addr> {
+        Listener::local_addr(&self.inner)
+    }
+}
+
+#[cfg(unix)]
+impl Listener for UnixListener {
+    type Io = tokio::net::UnixStream;
+
+    async fn accept(&mut self) -> io::Result<(Self::Io, PeerInfo)> {
+        let (conn, remote_addr) = UnixListener::accept(self).await?;
+        let mut peer = PeerInfo::new(
+            Addr::Unix(remote_addr.as_pathname().map(ToOwned::to_owned)),
+            Listener::local_addr(self)?,
+        );
+
+        if let Ok(cred) = conn.peer_cred() {
+            peer.insert(PeerCredentials::from(cred));
+        }
+
+        Ok((conn, peer))
+    }
+
+    fn local_addr(&self) -> io::Result<Addr> {
+        UnixListener::local_addr(self)
+            .map(|addr| Addr::Unix(addr.as_pathname().map(ToOwned::to_owned)))
+    }
+}
+
+/// A listener for in-memory connections, created by [`memory_listener`].
+#[derive(Debug)]
+pub struct MemoryListener(UnboundedReceiver<DuplexStream>);
+
+impl Listener for MemoryListener {
+    type Io = DuplexStream;
+
+    async fn accept(&mut self) -> io::Result<(Self::Io, PeerInfo)> {
+        match self.0.recv().await {
+            Some(conn) => Ok((conn, PeerInfo::new(Addr::Memory, Addr::Memory))),
+            // Every connector is gone, so no connection will ever arrive.
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Addr> {
+        Ok(Addr::Memory)
+    }
+}
+
+/// Opens in-memory connections to a server running on a [`MemoryListener`].
+/// This can be cloned to connect from multiple places.
+#[derive(Debug, Clone)]
+pub struct MemoryConnector(UnboundedSender<DuplexStream>);
+
+impl MemoryConnector {
+    /// Opens a new connection to the server. This fails if the listener has
+    /// been dropped.
+    pub fn connect(&self) -> io::Result<DuplexStream> {
+        let (client, server) = duplex(MEMORY_BUFFER_SIZE);
+
+        match self.0.send(server) {
+            Ok(()) => Ok(client),
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "the memory listener has been dropped",
+            )),
+        }
+    }
+}
+
+/// Creates a listener for in-memory connections and a connector used to open
+/// them. This allows running a server, e.g. in tests, without binding a port.
+pub fn memory_listener() -> (MemoryListener, MemoryConnector) {
+    let (tx, rx) = unbounded_channel();
+    (MemoryListener(rx), MemoryConnector(tx))
+}
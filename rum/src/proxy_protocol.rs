@@ -0,0 +1,510 @@
+//! Support for the HAProxy PROXY protocol.
+
+use crate::listener::{Addr, Listener, PeerInfo};
+use std::fmt::Debug;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+use tokio::task::JoinSet;
+
+/// The default amount of time a client is given to send the PROXY protocol
+/// header.
+pub const DEFAULT_HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The default maximum number of PROXY protocol headers being read at once.
+pub const DEFAULT_MAX_PENDING_HEADERS: usize = 1024;
+
+/// The signature that starts every version 2 header.
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The length of the fixed part of a version 2 header.
+const V2_HEADER_LEN: usize = 16;
+
+/// The prefix of every version 1 header.
+const V1_PREFIX: &[u8] = b"PROXY ";
+
+/// The maximum length of a version 1 header, including the trailing CRLF.
+const V1_MAX_LEN: usize = 107;
+
+/// The length of a Unix domain socket address in a version 2 header.
+const V2_UNIX_ADDR_LEN: usize = 108;
+
+/// Whether connections must start with a PROXY protocol header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ProxyProtocolMode {
+    /// Every connection must start with a valid header. Connections without
+    /// one are dropped. This should be used whenever all clients connect
+    /// through the proxy, as otherwise clients can connect directly and
+    /// claim any address.
+    #[default]
+    Required,
+    /// Connections may start with a header. Connections without one are
+    /// served as if they came directly from the client, but connections with
+    /// a malformed header are still dropped.
+    Optional,
+}
+
+/// The addresses of the proxy's connection to the server, recorded in
+/// [`PeerInfo`] for connections whose addresses were replaced by those from a
+/// PROXY protocol header.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProxyAddrs {
+    /// The address of the proxy.
+    pub remote_addr: Addr,
+    /// The local address the proxy connected to.
+    pub local_addr: Addr,
+}
+
+/// A parsed PROXY protocol header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Header {
+    /// The connection was proxied on behalf of a client.
+    Proxy {
+        /// The address of the original client.
+        source: Addr,
+        /// The address the original client connected to.
+        destination: Addr,
+    },
+    /// The connection was made by the proxy itself, e.g. for a health check,
+    /// or the original addresses are unknown.
+    Local,
+}
+
+/// The result of attempting to parse a header from the bytes read so far.
+#[derive(Debug)]
+enum Parse {
+    /// A complete header, and the number of bytes it took up.
+    Complete(Header, usize),
+    /// More bytes are needed.
+    Incomplete,
+    /// The connection does not start with a header.
+    Missing,
+}
+
+/// Creates the error returned for a malformed header.
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid PROXY protocol header: {}", msg),
+    )
+}
+
+/// Parses a version 1 header, if the buffer contains a complete line.
+fn parse_v1(buf: &[u8]) -> io::Result<Parse> {
+    let Some(end) = buf.windows(2).position(|window| window == b"\r\n") else {
+        return if buf.len() >= V1_MAX_LEN {
+            Err(invalid("the header is too long"))
+        } else {
+            Ok(Parse::Incomplete)
+        };
+    };
+
+    if end + 2 > V1_MAX_LEN {
+        return Err(invalid("the header is too long"));
+    }
+
+    let line = std::str::from_utf8(&buf[V1_PREFIX.len()..end])
+        .map_err(|_| invalid("the header is not valid text"))?;
+    let parts = line.split(' ').collect::<Vec<_>>();
+
+    let header = match parts[..] {
+        ["UNKNOWN", ..] => Header::Local,
+        [protocol @ ("TCP4" | "TCP6"), source, destination, source_port, destination_port] => {
+            let parse_ip = |ip: &str| -> io::Result<IpAddr> {
+                let ip = match protocol {
+                    "TCP4" => ip.parse::<Ipv4Addr>().map(IpAddr::V4),
+                    _ => ip.parse::<Ipv6Addr>().map(IpAddr::V6),
+                };
+                ip.map_err(|_| invalid("invalid address"))
+            };
+            let parse_port = |port: &str| -> io::Result<u16> {
+                // Ports must not have leading zeros.
+                match port.parse::<u16>() {
+                    Ok(value) if value.to_string().len() == port.len() => Ok(value),
+                    _ => Err(invalid("invalid port")),
+                }
+            };
+
+            Header::Proxy {
+                source: Addr::Tcp(SocketAddr::new(parse_ip(source)?, parse_port(source_port)?)),
+                destination: Addr::Tcp(SocketAddr::new(
+                    parse_ip(destination)?,
+                    parse_port(destination_port)?,
+                )),
+            }
+        }
+        _ => return Err(invalid("unsupported protocol or wrong number of fields")),
+    };
+
+    Ok(Parse::Complete(header, end + 2))
+}
+
+/// Parses a Unix domain socket address from a version 2 header.
+fn parse_v2_unix_addr(addr: &[u8]) -> Addr {
+    let len = addr.iter().position(|&b| b == 0).unwrap_or(addr.len());
+
+    if len == 0 {
+        return Addr::Unix(None);
+    }
+
+    #[cfg(unix)]
+    let path = {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(&addr[..len]))
+    };
+    #[cfg(not(unix))]
+    let path = PathBuf::from(String::from_utf8_lossy(&addr[..len]).into_owned());
+
+    Addr::Unix(Some(path))
+}
+
+/// Parses a version 2 header, if the buffer contains all of it.
+fn parse_v2(buf: &[u8]) -> io::Result<Parse> {
+    if buf.len() < V2_HEADER_LEN {
+        return Ok(Parse::Incomplete);
+    }
+
+    let version_command = buf[12];
+    let family_protocol = buf[13];
+    let len = V2_HEADER_LEN + usize::from(u16::from_be_bytes([buf[14], buf[15]]));
+
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported version"));
+    }
+
+    if buf.len() < len {
+        return Ok(Parse::Incomplete);
+    }
+
+    let addrs = &buf[V2_HEADER_LEN..len];
+
+    let header = match version_command & 0x0F {
+        // LOCAL: the addresses, if any, are ignored.
+        0x0 => Header::Local,
+        0x1 => {
+            // Only stream connections can carry HTTP.
+            match family_protocol & 0x0F {
+                // UNSPEC or STREAM.
+                0x0 | 0x1 => {}
+                _ => return Err(invalid("unsupported transport protocol")),
+            }
+
+            let (source, destination) = match family_protocol >> 4 {
+                // AF_UNSPEC: the addresses are unknown.
+                0x0 => return Ok(Parse::Complete(Header::Local, len)),
+                0x1 if addrs.len() >= 12 => {
+                    let ip = |i: usize| {
+                        IpAddr::V4(Ipv4Addr::from(
+                            <[u8; 4]>::try_from(&addrs[i..i + 4]).unwrap(),
+                        ))
+                    };
+                    let port = |i: usize| u16::from_be_bytes([addrs[i], addrs[i + 1]]);
+
+                    (
+                        Addr::Tcp(SocketAddr::new(ip(0), port(8))),
+                        Addr::Tcp(SocketAddr::new(ip(4), port(10))),
+                    )
+                }
+                0x2 if addrs.len() >= 36 => {
+                    let ip = |i: usize| {
+                        IpAddr::V6(Ipv6Addr::from(
+                            <[u8; 16]>::try_from(&addrs[i..i + 16]).unwrap(),
+                        ))
+                    };
+                    let port = |i: usize| u16::from_be_bytes([addrs[i], addrs[i + 1]]);
+
+                    (
+                        Addr::Tcp(SocketAddr::new(ip(0), port(32))),
+                        Addr::Tcp(SocketAddr::new(ip(16), port(34))),
+                    )
+                }
+                0x3 if addrs.len() >= 2 * V2_UNIX_ADDR_LEN => (
+                    parse_v2_unix_addr(&addrs[..V2_UNIX_ADDR_LEN]),
+                    parse_v2_unix_addr(&addrs[V2_UNIX_ADDR_LEN..2 * V2_UNIX_ADDR_LEN]),
+                ),
+                0x1..=0x3 => return Err(invalid("the address block is too short")),
+                _ => return Err(invalid("unsupported address family")),
+            };
+
+            Header::Proxy {
+                source,
+                destination,
+            }
+        }
+        _ => return Err(invalid("unsupported command")),
+    };
+
+    Ok(Parse::Complete(header, len))
+}
+
+/// Attempts to parse a header from the bytes read so far.
+fn parse(buf: &[u8]) -> io::Result<Parse> {
+    let prefix_len = buf.len().min(V2_SIGNATURE.len());
+
+    if buf[..prefix_len] == V2_SIGNATURE[..prefix_len] {
+        return if prefix_len == V2_SIGNATURE.len() {
+            parse_v2(buf)
+        } else {
+            Ok(Parse::Incomplete)
+        };
+    }
+
+    let prefix_len = buf.len().min(V1_PREFIX.len());
+
+    if buf[..prefix_len] == V1_PREFIX[..prefix_len] {
+        return if prefix_len == V1_PREFIX.len() {
+            parse_v1(buf)
+        } else {
+            Ok(Parse::Incomplete)
+        };
+    }
+
+    Ok(Parse::Missing)
+}
+
+/// A connection accepted by a [`ProxyProtocolListener`]. Any bytes read past
+/// the PROXY protocol header are returned before reading from the connection
+/// again.
+#[derive(Debug)]
+pub struct ProxyProtocolStream<I> {
+    /// The bytes read past the header.
+    buf: Vec<u8>,
+    /// The position of the next unreturned byte in `buf`.
+    pos: usize,
+    /// The underlying connection.
+    inner: I,
+}
+
+impl<I> ProxyProtocolStream<I> {
+    /// Gets a reference to the underlying connection.
+    pub fn get_ref(&self) -> &I {
+        &self.inner
+    }
+}
+
+impl<I> AsyncRead for ProxyProtocolStream<I>
+where
+    I: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.pos < self.buf.len() {
+            let len = buf.remaining().min(self.buf.len() - self.pos);
+            let pos = self.pos;
+            buf.put_slice(&self.buf[pos..pos + len]);
+            self.pos += len;
+
+            if self.pos == self.buf.len() {
+                self.buf = Vec::new();
+                self.pos = 0;
+            }
+
+            return Poll::Ready(Ok(()));
+        }
+
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<I> AsyncWrite for ProxyProtocolStream<I>
+where
+    I: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// A listener that reads a PROXY protocol header, version 1 or 2, from the
+/// start of every connection accepted by an inner listener, before HTTP is
+/// served on it. The original source and destination addresses from the
+/// header replace those of the connection in the [`PeerInfo`] available to
+/// requests, and the proxy's own addresses are recorded as [`ProxyAddrs`].
+///
+/// Headers are read concurrently in the background, so a slow client does
+/// not hold up other connections. Connections whose header is malformed, or
+/// missing in [`ProxyProtocolMode::Required`] mode, or that do not send it in
+/// time, are dropped. Once the maximum number of headers is being read, no
+/// more connections are accepted from the inner listener until one of them
+/// finishes. When combined with TLS, this listener must be the inner
+/// one, since the header is sent before the TLS handshake.
+pub struct ProxyProtocolListener<L>
+where
+    L: Listener,
+{
+    /// The inner listener.
+    inner: L,
+    /// Whether a header is required.
+    mode: ProxyProtocolMode,
+    /// The amount of time a client is given to send the header.
+    header_timeout: Duration,
+    /// The maximum number of headers being read at once.
+    max_pending_headers: usize,
+    /// The headers currently being read.
+    headers: JoinSet<io::Result<(ProxyProtocolStream<L::Io>, PeerInfo)>>,
+}
+
+impl<L> ProxyProtocolListener<L>
+where
+    L: Listener,
+{
+    /// Wraps a listener to read PROXY protocol headers from its connections,
+    /// requiring every connection to start with one.
+    pub fn new(inner: L) -> Self {
+        Self {
+            inner,
+            mode: ProxyProtocolMode::default(),
+            header_timeout: DEFAULT_HEADER_TIMEOUT,
+            max_pending_headers: DEFAULT_MAX_PENDING_HEADERS,
+            headers: JoinSet::new(),
+        }
+    }
+
+    /// Sets whether connections must start with a header.
+    pub fn with_mode(mut self, mode: ProxyProtocolMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the amount of time a client is given to send the header. The
+    /// default is [`DEFAULT_HEADER_TIMEOUT`].
+    pub fn with_header_timeout(mut self, timeout: Duration) -> Self {
+        self.header_timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum number of headers being read at once. A limit of
+    /// zero is treated as one. The default is
+    /// [`DEFAULT_MAX_PENDING_HEADERS`].
+    pub fn with_max_pending_headers(mut self, max: usize) -> Self {
+        self.max_pending_headers = max.max(1);
+        self
+    }
+
+    /// Reads the header from the start of a connection, replacing the
+    /// connection's addresses with those from the header.
+    async fn read_header(
+        mut conn: L::Io,
+        mut peer: PeerInfo,
+        mode: ProxyProtocolMode,
+    ) -> io::Result<(ProxyProtocolStream<L::Io>, PeerInfo)> {
+        let mut buf = Vec::with_capacity(V1_MAX_LEN);
+
+        let (header, len) = loop {
+            match parse(&buf)? {
+                Parse::Complete(header, len) => break (Some(header), len),
+                Parse::Missing if mode == ProxyProtocolMode::Optional => break (None, 0),
+                Parse::Missing => return Err(invalid("the header is missing")),
+                Parse::Incomplete => {
+                    if conn.read_buf(&mut buf).await? == 0 {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                }
+            }
+        };
+
+        if let Some(Header::Proxy {
+            source,
+            destination,
+        }) = header
+        {
+            let proxy = ProxyAddrs {
+                remote_addr: peer.remote_addr().clone(),
+                local_addr: peer.local_addr().clone(),
+            };
+
+            peer.set_remote_addr(source);
+            peer.set_local_addr(destination);
+            peer.insert(proxy);
+        }
+
+        Ok((
+            ProxyProtocolStream {
+                buf: buf.split_off(len),
+                pos: 0,
+                inner: conn,
+            },
+            peer,
+        ))
+    }
+}
+
+impl<L> Listener for ProxyProtocolListener<L>
+where
+    L: Listener,
+{
+    type Io = ProxyProtocolStream<L::Io>;
+
+    async fn accept(&mut self) -> io::Result<(Self::Io, PeerInfo)> {
+        loop {
+            tokio::select! {
+                conn = self.inner.accept(), if self.headers.len() < self.max_pending_headers => {
+                    let (conn, peer) = conn?;
+                    let header = Self::read_header(conn, peer, self.mode);
+                    let timeout = self.header_timeout;
+
+                    self.headers.spawn(async move {
+                        tokio::time::timeout(timeout, header)
+                            .await
+                            .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))?
+                    });
+                }
+                Some(header) = self.headers.join_next(), if !self.headers.is_empty() => {
+                    if let Ok(Ok(conn)) = header {
+                        return Ok(conn);
+                    }
+                }
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Addr> {
+        self.inner.local_addr()
+    }
+}
+
+impl<L> Debug for ProxyProtocolListener<L>
+where
+    L: Listener + Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProxyProtocolListener")
+            .field("inner", &self.inner)
+            .field("mode", &self.mode)
+            .field("header_timeout", &self.header_timeout)
+            .field("max_pending_headers", &self.max_pending_headers)
+            .field("headers", &self.headers.len())
+            .finish()
+    }
+}
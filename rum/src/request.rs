@@ -1,14 +1,19 @@
 //! Types involving HTTP requests.
 
-use crate::body::{BodyRaw, BodyString, Json};
+use crate::body::{BodyRaw, BodyStream, BodyString, Json};
+#[cfg(unix)]
+use crate::connection::PeerCredentials;
+use crate::connection::{ConnectInfo, ConnectionState};
 #[cfg(feature = "nightly")]
 use crate::cookie::{Cookie, CookieOptional};
 use crate::cookie::{CookieMap, Cookies, ParseCookie};
 use crate::error::{Error, Result};
+use crate::forwarded::{self, ClientIp, RequestOrigin};
 #[cfg(feature = "nightly")]
 use crate::header::{Header, HeaderOptional};
 use crate::header::{HeaderMap, Headers, ParseHeader};
-use crate::http::Method;
+use crate::http::{Method, Version};
+use crate::listener::PeerInfo;
 use crate::middleware::NextFn;
 #[cfg(feature = "nightly")]
 use crate::path::PathParam;
@@ -16,26 +21,46 @@ use crate::path::{ParsePathParam, PathParamMap, PathParams};
 use crate::query::{ParseQueryParam, QueryParamMap, QueryParams};
 #[cfg(feature = "nightly")]
 use crate::query::{QueryParam, QueryParamBool, QueryParamOptional};
-use crate::routing::{RoutePath, RoutePathMatched, RoutePathMatchedSegment, RoutePathString};
+use crate::routing::{
+    RouteConfig, RoutePath, RoutePathMatched, RoutePathMatchedSegment, RoutePathString,
+};
+use crate::sse::LastEventId;
 use crate::state::{LocalState, State, StateManager};
-use http::header::COOKIE;
-use http_body_util::BodyExt;
+#[cfg(feature = "tls")]
+use crate::tls::ClientCertificate;
+use crate::upgrade::OnUpgrade;
+#[cfg(feature = "websocket")]
+use crate::websocket::WebSocketUpgrade;
+use http::header::{CONTENT_LENGTH, COOKIE};
+use http_body_util::{BodyExt, Limited};
 use hyper::body::Incoming;
 use hyper::Request as HyperRequest;
 use serde::de::DeserializeOwned;
 use std::any::type_name;
 use std::borrow::Borrow;
 use std::collections::HashMap;
+use std::net::IpAddr;
 use std::ops::Deref;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, PoisonError};
 
 /// The internal request type.
 #[derive(Debug, Clone)]
 pub struct RequestInner {
     /// The raw request body.
     body: Arc<[u8]>,
+    /// The streamed request body, on routes that stream request bodies,
+    /// until it is extracted.
+    body_stream: Arc<Mutex<Option<BodyStream>>>,
+    /// The pending connection upgrade, until it is taken.
+    upgrade: Arc<Mutex<Option<OnUpgrade>>>,
     /// The request method.
     method: Method,
+    /// The HTTP protocol version.
+    version: Version,
+    /// The request target scheme, if the target is in absolute form.
+    scheme: Option<String>,
+    /// The request target authority, as used by `CONNECT` requests.
+    authority: Option<String>,
     /// The request path.
     path: RoutePath,
     /// The matched path parameters.
@@ -52,6 +77,8 @@ pub struct RequestInner {
     state: StateManager,
     /// The local state manager.
     local_state: LocalState,
+    /// Facts about the connection the request arrived on.
+    connection: ConnectionState,
 }
 
 impl RequestInner {
@@ -60,12 +87,57 @@ impl RequestInner {
         req: HyperRequest<Incoming>,
         matched_path: RoutePathMatched,
         state: StateManager,
+        connection: ConnectionState,
+        config: &RouteConfig,
     ) -> Result<Self> {
-        let (head, body) = req.into_parts();
+        let (mut head, body) = req.into_parts();
+        let upgrade = head
+            .extensions
+            .remove::<hyper::upgrade::OnUpgrade>()
+            .map(OnUpgrade);
+        let max_body_size = config.max_body_size();
+
+        // Reject a body that is declared to be too large before reading any
+        // of it. Since the body is never polled, clients waiting on
+        // `Expect: 100-continue` are not told to send it.
+        let content_length = head
+            .headers
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse::<u64>().ok());
+
+        if content_length.is_some_and(|content_length| content_length > max_body_size) {
+            return Err(Error::PayloadTooLarge(max_body_size));
+        }
+
+        let (body, body_stream) = if config.streaming_body() {
+            let body_stream = BodyStream::new(body, max_body_size, config.body_read_timeout());
+            (Arc::from([]), Some(body_stream))
+        } else {
+            let body =
+                Limited::new(body, usize::try_from(max_body_size).unwrap_or(usize::MAX)).collect();
+            let body = match config.body_read_timeout() {
+                Some(timeout) => tokio::time::timeout(timeout, body)
+                    .await
+                    .map_err(|_| Error::RequestTimeout)?,
+                None => body.await,
+            }
+            .map_err(|err| match err.downcast::<hyper::Error>() {
+                Ok(err) => Error::ServerError(*err),
+                // The only other error is a `LengthLimitError`.
+                Err(_) => Error::PayloadTooLarge(max_body_size),
+            })?;
+
+            (Arc::from(body.to_bytes().to_vec()), None)
+        };
 
         Ok(Self {
-            body: Arc::from(body.collect().await?.to_bytes().to_vec()),
+            body,
+            body_stream: Arc::new(Mutex::new(body_stream)),
+            upgrade: Arc::new(Mutex::new(upgrade)),
             method: Method::from(&head.method),
+            version: head.version,
+            scheme: head.uri.scheme_str().map(ToOwned::to_owned),
+            authority: head.uri.authority().map(ToString::to_string),
             path: RoutePath::from(head.uri.path()),
             matched_path: matched_path.clone(),
             path_params: PathParamMap(Arc::new(
@@ -107,6 +179,7 @@ impl RequestInner {
             )),
             state,
             local_state: LocalState::new(),
+            connection,
         })
     }
 
@@ -115,6 +188,28 @@ impl RequestInner {
         &self.body
     }
 
+    /// Takes the streamed request body, on routes that stream request bodies.
+    /// This returns `None` on other routes, or if the stream has already been
+    /// taken.
+    pub fn take_body_stream(&self) -> Option<BodyStream> {
+        self.body_stream
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+
+    /// Takes the pending upgrade of the connection to another protocol, or
+    /// the tunnel of a `CONNECT` request. This returns `None` if the
+    /// connection cannot be upgraded, e.g. because the request is not a
+    /// `CONNECT` request and was made using HTTP/2, or if the upgrade has
+    /// already been taken.
+    pub fn take_upgrade(&self) -> Option<OnUpgrade> {
+        self.upgrade
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+
     /// Gets the request body as a string.
     pub fn body_str(&self) -> Result<&str> {
         Ok(std::str::from_utf8(&self.body)?)
@@ -133,6 +228,17 @@ impl RequestInner {
         &self.method
     }
 
+    /// Gets the HTTP protocol version the request was made with.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Gets the authority of the request target, if present. For `CONNECT`
+    /// requests, this is the address the client wants to tunnel to.
+    pub fn authority(&self) -> Option<&str> {
+        self.authority.as_deref()
+    }
+
     /// Gets the request path.
     pub fn path(&self) -> RoutePath {
         self.path.clone()
@@ -256,6 +362,55 @@ impl RequestInner {
     pub fn local_state(&self) -> LocalState {
         self.local_state.clone()
     }
+
+    /// Gets the addresses, ID and protocol of the connection the request
+    /// arrived on, and the position of the request on it.
+    pub fn connect_info(&self) -> ConnectInfo {
+        self.connection.connect_info(self.version)
+    }
+
+    /// Resolves the IP address of the client that originally made the
+    /// request, following the forwarding headers of trusted proxies. See
+    /// [`ClientIp`] for details.
+    pub fn client_ip(&self) -> Result<IpAddr> {
+        forwarded::client_ip(
+            &self.connection,
+            self.connection.trusted_proxies(),
+            &self.headers,
+        )
+    }
+
+    /// Resolves the scheme and host of the URL the client originally
+    /// requested, following the forwarding headers of trusted proxies. See
+    /// [`RequestOrigin`] for details.
+    pub fn origin(&self) -> RequestOrigin {
+        forwarded::origin(
+            &self.connection,
+            self.connection.trusted_proxies(),
+            &self.headers,
+            self.scheme.as_deref(),
+            self.authority.as_deref(),
+        )
+    }
+
+    /// Gets information about the connection the request arrived on.
+    pub fn peer_info(&self) -> &PeerInfo {
+        &self.connection
+    }
+
+    /// Gets the credentials of the peer process, if the request arrived on a
+    /// Unix domain socket.
+    #[cfg(unix)]
+    pub fn peer_credentials(&self) -> Option<PeerCredentials> {
+        self.connection.get().copied()
+    }
+
+    /// Gets the verified certificate presented by the client during the TLS
+    /// handshake, if there was one.
+    #[cfg(feature = "tls")]
+    pub fn client_certificate(&self) -> Option<ClientCertificate> {
+        self.connection.get().cloned()
+    }
 }
 
 /// An HTTP request. Typically, direct interaction with this type is
@@ -274,9 +429,11 @@ impl Request {
         req: HyperRequest<Incoming>,
         matched_path: RoutePathMatched,
         state: StateManager,
+        connection: ConnectionState,
+        config: &RouteConfig,
     ) -> Result<Self> {
         Ok(Self {
-            inner: Arc::new(RequestInner::new(req, matched_path, state).await?),
+            inner: Arc::new(RequestInner::new(req, matched_path, state, connection, config).await?),
             next: None,
         })
     }
@@ -327,6 +484,12 @@ impl FromRequest for BodyRaw {
     }
 }
 
+impl FromRequest for BodyStream {
+    fn from_request(req: &Request) -> Result<Self> {
+        req.take_body_stream().ok_or(Error::BodyStreamUnavailable)
+    }
+}
+
 impl FromRequest for BodyString {
     fn from_request(req: &Request) -> Result<Self> {
         match req.header_optional("Content-Type") {
@@ -366,6 +529,12 @@ impl FromRequest for Method {
     }
 }
 
+impl FromRequest for Version {
+    fn from_request(req: &Request) -> Result<Self> {
+        Ok(req.version)
+    }
+}
+
 impl FromRequest for RoutePathString {
     fn from_request(req: &Request) -> Result<Self> {
         Ok(Self(req.path.to_string()))
@@ -492,6 +661,15 @@ where
     }
 }
 
+impl FromRequest for LastEventId {
+    fn from_request(req: &Request) -> Result<Self> {
+        Ok(Self(
+            req.header_optional("Last-Event-ID")
+                .and_then(|values| values.first().cloned()),
+        ))
+    }
+}
+
 impl FromRequest for CookieMap {
     fn from_request(req: &Request) -> Result<Self> {
         Ok(req.cookies.clone())
@@ -544,6 +722,52 @@ impl FromRequest for LocalState {
     }
 }
 
+#[cfg(feature = "tls")]
+impl FromRequest for ClientCertificate {
+    fn from_request(req: &Request) -> Result<Self> {
+        req.client_certificate()
+            .ok_or(Error::MissingClientCertificate)
+    }
+}
+
+#[cfg(unix)]
+impl FromRequest for PeerCredentials {
+    fn from_request(req: &Request) -> Result<Self> {
+        req.peer_credentials().ok_or(Error::MissingPeerCredentials)
+    }
+}
+
+impl FromRequest for OnUpgrade {
+    fn from_request(req: &Request) -> Result<Self> {
+        req.take_upgrade().ok_or(Error::UpgradeUnavailable)
+    }
+}
+
+#[cfg(feature = "websocket")]
+impl FromRequest for WebSocketUpgrade {
+    fn from_request(req: &Request) -> Result<Self> {
+        WebSocketUpgrade::new(req)
+    }
+}
+
+impl FromRequest for ConnectInfo {
+    fn from_request(req: &Request) -> Result<Self> {
+        Ok(req.connect_info())
+    }
+}
+
+impl FromRequest for ClientIp {
+    fn from_request(req: &Request) -> Result<Self> {
+        Ok(Self(req.client_ip()?))
+    }
+}
+
+impl FromRequest for RequestOrigin {
+    fn from_request(req: &Request) -> Result<Self> {
+        Ok(req.origin())
+    }
+}
+
 impl FromRequest for NextFn {
     fn from_request(req: &Request) -> Result<Self> {
         match &req.next {
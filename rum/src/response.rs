@@ -1,9 +1,10 @@
 //! Types involving HTTP responses.
 
-use crate::body::{BodyString, Json};
+use crate::body::{BodyRaw, BodyString, Bytes, Json, ResponseBody, StreamBody};
 use crate::cookie::SetCookie;
 use crate::error::{Error, ErrorSource, Result};
 use crate::http::StatusCode;
+use crate::sse::Sse;
 use http::header::SET_COOKIE;
 use hyper::Response as HyperResponse;
 use serde::Serialize;
@@ -41,7 +42,7 @@ pub struct ResponseInner {
     /// The response status code.
     pub code: Option<StatusCode>,
     /// The response body.
-    pub body: Option<String>,
+    pub body: Option<ResponseBody>,
     /// The response headers.
     pub headers: Option<HashMap<String, Vec<String>>>,
     /// The cookies.
@@ -84,7 +85,7 @@ impl Response {
     /// Sets the response body string content.
     pub fn body(mut self, body: &str) -> Self {
         if let Self::Ok(inner) = &mut self {
-            inner.body = Some(body.to_owned());
+            inner.body = Some(ResponseBody::Bytes(Bytes::copy_from_slice(body.as_bytes())));
         }
 
         self
@@ -93,14 +94,42 @@ impl Response {
     /// Sets the response body string content if the body is empty.
     pub fn body_or(mut self, body: &str) -> Self {
         if let Self::Ok(inner) = &mut self {
-            if inner.body.as_ref().map(String::is_empty).unwrap_or(true) {
-                inner.body = Some(body.to_owned());
+            let empty = match &inner.body {
+                Some(ResponseBody::Bytes(bytes)) => bytes.is_empty(),
+                Some(ResponseBody::Stream(_)) => false,
+                None => true,
+            };
+
+            if empty {
+                inner.body = Some(ResponseBody::Bytes(Bytes::copy_from_slice(body.as_bytes())));
             }
         }
 
         self
     }
 
+    /// Sets the response body binary content.
+    pub fn body_bytes<B>(mut self, body: B) -> Self
+    where
+        B: Into<Bytes>,
+    {
+        if let Self::Ok(inner) = &mut self {
+            inner.body = Some(ResponseBody::Bytes(body.into()));
+        }
+
+        self
+    }
+
+    /// Sets the response body to a stream, which is sent to the client using
+    /// chunked transfer encoding as it is produced.
+    pub fn body_stream(mut self, body: StreamBody) -> Self {
+        if let Self::Ok(inner) = &mut self {
+            inner.body = Some(ResponseBody::Stream(body));
+        }
+
+        self
+    }
+
     /// Sets the response body JSON content.
     pub fn body_json<T>(mut self, body: T) -> Self
     where
@@ -109,7 +138,7 @@ impl Response {
         if let Self::Ok(inner) = &mut self {
             match serde_json::to_string(&body) {
                 Ok(body) => {
-                    inner.body = Some(body);
+                    inner.body = Some(body.into());
                 }
                 Err(err) => {
                     self = Self::Err(Arc::new(Error::ServerJsonError(err)));
@@ -194,8 +223,8 @@ impl Default for Response {
 }
 
 #[allow(clippy::from_over_into)]
-impl Into<HyperResponse<String>> for Response {
-    fn into(self) -> HyperResponse<String> {
+impl Into<HyperResponse<ResponseBody>> for Response {
+    fn into(self) -> HyperResponse<ResponseBody> {
         let (code, body, headers, cookies) = match self {
             Self::Ok(inner) => (
                 inner.code.unwrap_or_default(),
@@ -210,8 +239,13 @@ impl Into<HyperResponse<String>> for Response {
                     ErrorSource::Server => "An internal error occurred".to_owned(),
                 })
                 .to_json()
-                .unwrap(),
-                HashMap::new(),
+                .unwrap()
+                .into(),
+                // Keep headers the error requires, such as `Allow`.
+                match err.as_response() {
+                    Self::Ok(inner) => inner.headers.unwrap_or_default(),
+                    Self::Err(_) => HashMap::new(),
+                },
                 Vec::new(),
             ),
         };
@@ -281,6 +315,49 @@ where
     }
 }
 
+impl IntoResponse for Bytes {
+    fn into_response(self) -> Response {
+        Response::new()
+            .body_bytes(self)
+            .header("Content-Type", "application/octet-stream")
+    }
+}
+
+impl IntoResponse for Vec<u8> {
+    fn into_response(self) -> Response {
+        Bytes::from(self).into_response()
+    }
+}
+
+impl IntoResponse for &[u8] {
+    fn into_response(self) -> Response {
+        Bytes::copy_from_slice(self).into_response()
+    }
+}
+
+impl IntoResponse for BodyRaw {
+    fn into_response(self) -> Response {
+        Bytes::copy_from_slice(&self).into_response()
+    }
+}
+
+impl IntoResponse for StreamBody {
+    fn into_response(self) -> Response {
+        Response::new()
+            .body_stream(self)
+            .header("Content-Type", "application/octet-stream")
+    }
+}
+
+impl IntoResponse for Sse {
+    fn into_response(self) -> Response {
+        Response::new()
+            .body_stream(StreamBody::new(self.into_stream()))
+            .header("Content-Type", "text/event-stream")
+            .header("Cache-Control", "no-cache")
+    }
+}
+
 impl IntoResponse for StatusCode {
     fn into_response(self) -> Response {
         Response::new().status_code(self)
@@ -13,6 +13,7 @@ use std::ops::{Bound, Deref, DerefMut, RangeBounds};
 use std::pin::Pin;
 use std::slice::Iter;
 use std::sync::Arc;
+use std::time::Duration;
 use std::vec::IntoIter;
 
 /// A segment of a route path.
@@ -290,6 +291,19 @@ impl RoutePathMatched {
             .split_first()
             .map(|(first, rest)| (first.to_owned(), Self::from(rest)))
     }
+
+    /// Returns the route path that was matched, with the names of wildcard
+    /// segments in place of their values, e.g. `/users/{id}`.
+    pub fn route_path(&self) -> RoutePath {
+        self.iter()
+            .map(|segment| match segment {
+                RoutePathMatchedSegment::Static(name) => RoutePathSegment::Static(name.clone()),
+                RoutePathMatchedSegment::Wildcard(name, _) => {
+                    RoutePathSegment::Wildcard(name.clone())
+                }
+            })
+            .collect()
+    }
 }
 
 impl Default for RoutePathMatched {
@@ -394,12 +408,109 @@ impl BorrowMut<String> for RoutePathString {
     }
 }
 
+/// The default maximum size of a request body, in bytes.
+pub const DEFAULT_MAX_BODY_SIZE: u64 = 2 * 1024 * 1024;
+
+/// Per-route request handling settings. Settings that are not configured on a
+/// route are inherited from the enclosing route groups, and ultimately from
+/// the server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct RouteConfig {
+    /// The maximum size of a request body, in bytes.
+    max_body_size: Option<u64>,
+    /// The maximum amount of time to spend reading a request body.
+    body_read_timeout: Option<Duration>,
+    /// The maximum amount of time a handler, including its middleware, may
+    /// take to produce a response.
+    handler_timeout: Option<Duration>,
+    /// Whether the request body is streamed to the handler rather than
+    /// buffered.
+    streaming_body: Option<bool>,
+}
+
+impl RouteConfig {
+    /// Creates a new route configuration with nothing configured.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Configures the maximum size of a request body, in bytes. Requests with
+    /// larger bodies are rejected with a `413 Payload Too Large` response.
+    pub fn with_max_body_size(mut self, max_body_size: u64) -> Self {
+        self.max_body_size = Some(max_body_size);
+        self
+    }
+
+    /// Configures the maximum amount of time to spend reading a request body.
+    /// Requests whose bodies take longer to arrive are rejected with a
+    /// `408 Request Timeout` response.
+    pub fn with_body_read_timeout(mut self, timeout: Duration) -> Self {
+        self.body_read_timeout = Some(timeout);
+        self
+    }
+
+    /// Configures the maximum amount of time a handler, including its
+    /// middleware, may take to produce a response. Handlers that take longer
+    /// are cancelled, and a `503 Service Unavailable` response is sent
+    /// instead.
+    pub fn with_handler_timeout(mut self, timeout: Duration) -> Self {
+        self.handler_timeout = Some(timeout);
+        self
+    }
+
+    /// Configures whether the request body is streamed to the handler through
+    /// a [`BodyStream`](crate::body::BodyStream) rather than buffered before
+    /// the handler runs. On streaming routes, buffered body extractors such
+    /// as [`BodyRaw`](crate::body::BodyRaw) see an empty body.
+    pub fn with_streaming_body(mut self, streaming_body: bool) -> Self {
+        self.streaming_body = Some(streaming_body);
+        self
+    }
+
+    /// Gets the maximum size of a request body, in bytes, falling back to
+    /// [`DEFAULT_MAX_BODY_SIZE`] if none is configured.
+    pub fn max_body_size(&self) -> u64 {
+        self.max_body_size.unwrap_or(DEFAULT_MAX_BODY_SIZE)
+    }
+
+    /// Gets the maximum amount of time to spend reading a request body, if
+    /// one is configured.
+    pub fn body_read_timeout(&self) -> Option<Duration> {
+        self.body_read_timeout
+    }
+
+    /// Gets the maximum amount of time a handler may take to produce a
+    /// response, if one is configured.
+    pub fn handler_timeout(&self) -> Option<Duration> {
+        self.handler_timeout
+    }
+
+    /// Gets whether the request body is streamed to the handler. By default,
+    /// it is buffered.
+    pub fn streaming_body(&self) -> bool {
+        self.streaming_body.unwrap_or(false)
+    }
+
+    /// Fills in every setting not configured on `self` from `parent`.
+    pub(crate) fn inherit(self, parent: &Self) -> Self {
+        Self {
+            max_body_size: self.max_body_size.or(parent.max_body_size),
+            body_read_timeout: self.body_read_timeout.or(parent.body_read_timeout),
+            handler_timeout: self.handler_timeout.or(parent.handler_timeout),
+            streaming_body: self.streaming_body.or(parent.streaming_body),
+        }
+    }
+}
+
 /// A shareable route handler.
 #[allow(clippy::type_complexity)]
 #[derive(Clone)]
-pub struct RouteHandler(
-    pub(crate) Arc<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync>,
-);
+pub struct RouteHandler {
+    /// The handler function.
+    handler: Arc<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync>,
+    /// The settings specific to this route.
+    config: RouteConfig,
+}
 
 impl RouteHandler {
     /// Creates a new route handler from the provided function.
@@ -408,12 +519,27 @@ impl RouteHandler {
         F: Fn(Request) -> Fut + Send + Sync + 'static,
         Fut: Future<Output = Response> + Send + 'static,
     {
-        Self(Arc::new(move |req| Box::pin(route(req))))
+        Self {
+            handler: Arc::new(move |req| Box::pin(route(req))),
+            config: RouteConfig::new(),
+        }
+    }
+
+    /// Configures settings specific to this route, overriding those of the
+    /// enclosing route groups and the server.
+    pub fn with_config(mut self, config: RouteConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Gets the settings of this route.
+    pub fn config(&self) -> &RouteConfig {
+        &self.config
     }
 
     /// Calls the handler.
     pub(crate) async fn call(&self, req: Request) -> Response {
-        (self.0)(req).await
+        (self.handler)(req).await
     }
 }
 
@@ -482,6 +608,11 @@ impl CompleteRouteHandler {
         Self::generate_invoker_recursive(&middleware, handler)
     }
 
+    /// Gets the settings of this route.
+    pub fn config(&self) -> &RouteConfig {
+        self.handler.config()
+    }
+
     /// Calls the handler and all middleware.
     pub(crate) async fn call(&self, req: Request) -> Response {
         self.invoker.call(req).await
@@ -666,6 +797,8 @@ pub struct RouteGroup {
     pub(crate) groups: Vec<Self>,
     /// The collection of all registered middleware.
     pub(crate) middleware: MiddlewareCollection,
+    /// The settings applied to all routes in the group.
+    pub(crate) config: RouteConfig,
 }
 
 impl RouteGroup {
@@ -679,6 +812,7 @@ impl RouteGroup {
             routes: HashMap::new(),
             groups: Vec::new(),
             middleware: MiddlewareCollection::new(),
+            config: RouteConfig::new(),
         }
     }
 
@@ -794,6 +928,13 @@ impl RouteGroup {
         self
     }
 
+    /// Configures settings applied to all routes in this group and all route
+    /// groups below, unless overridden by a route or a route group below.
+    pub fn with_config(mut self, config: RouteConfig) -> Self {
+        self.config = config;
+        self
+    }
+
     /// Registers middleware to be used on all routes at this level, but not on
     /// route groups below.
     pub fn with_local_middleware<M>(mut self, middleware: M) -> Self
@@ -825,7 +966,8 @@ impl RouteGroup {
 
         let mut route_level = RouteLevel::new();
 
-        for ((method, path), handler) in self.routes {
+        for ((method, path), mut handler) in self.routes {
+            handler.config = handler.config.inherit(&self.config);
             route_level.add(
                 method,
                 path,
@@ -833,7 +975,8 @@ impl RouteGroup {
             );
         }
 
-        for group in self.groups {
+        for mut group in self.groups {
+            group.config = group.config.inherit(&self.config);
             route_level.add_group(group, recursive_middleware.clone());
         }
 
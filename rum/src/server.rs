@@ -1,53 +1,163 @@
 //! HTTP server building types.
 
+#[cfg(all(unix, feature = "socket-activation"))]
+use crate::activation::{InheritedListener, InheritedListeners};
+use crate::body::ResponseBody;
+use crate::connection::ConnectionState;
 use crate::error::Error;
-use crate::http::Method;
+use crate::event::{EventSender, RequestInfo, ServerEvent};
+use crate::forwarded::TrustedProxies;
+use crate::http::{Method, StatusCode};
+use crate::listener::{Addr, Listener, PeerInfo, SocketListener};
 use crate::middleware::Middleware;
 use crate::request::Request;
 use crate::response::Response;
 use crate::routing::{RouteGroup, RouteHandler, RouteLevel, RoutePath};
-use crate::state::StateManager;
+use crate::state::{ServerState, StateManager};
+#[cfg(feature = "tls")]
+use crate::tls::{TlsConfig, TlsListener};
 use crate::typemap::TypeMap;
 use hyper::body::Incoming;
+use hyper::header::{HeaderValue, SERVER};
 use hyper::service::Service;
 use hyper::{Request as HyperRequest, Response as HyperResponse};
+use hyper_util::rt::TokioTimer;
 use hyper_util::rt::{TokioExecutor, TokioIo};
 use hyper_util::server::conn::auto::Builder;
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
 use std::future::Future;
 use std::io;
+use std::net::SocketAddr;
+#[cfg(all(unix, feature = "socket-activation"))]
+use std::os::fd::OwnedFd;
+#[cfg(unix)]
+use std::path::Path;
 use std::pin::Pin;
-use std::sync::Arc;
-use tokio::net::{TcpListener, ToSocketAddrs};
-use tokio::sync::mpsc::{
-    channel, unbounded_channel, Receiver, Sender, UnboundedReceiver, UnboundedSender,
-};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::{TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{oneshot, watch, OwnedSemaphorePermit, Semaphore};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::Instant;
 
-/// The sending half of a server shutdown signal channel.
-#[derive(Debug)]
-pub struct ShutdownSender(Sender<()>);
+/// The default amount of time given to in-flight connections to finish after
+/// a shutdown signal is received.
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The default amount of time clients are told to wait before retrying
+/// requests rejected because the server is overloaded.
+pub const DEFAULT_OVERLOAD_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// The initial amount of time to wait before accepting again after a listener
+/// fails.
+const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(5);
+
+/// The maximum amount of time to wait before accepting again after repeated
+/// listener failures.
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The smallest read buffer `hyper` accepts for HTTP/1 connections. Smaller
+/// header size limits are enforced by the server itself.
+const MIN_READ_BUFFER_SIZE: usize = 8192;
+
+/// The default maximum number of connections waiting to be accepted on
+/// sockets bound by the server.
+pub const DEFAULT_BACKLOG: u32 = 1024;
+
+/// The sending half of a server shutdown signal channel. This can be cloned
+/// so that multiple parts of an application can each trigger the shutdown.
+#[derive(Debug, Clone)]
+pub struct ShutdownSender(Arc<watch::Sender<bool>>);
 
 impl ShutdownSender {
-    /// Sends a shutdown signal to the server.
-    pub async fn shutdown(self) -> bool {
-        self.0.send(()).await.is_ok()
+    /// Sends a shutdown signal to the server. Returns `false` if the server
+    /// is no longer listening for the signal.
+    pub async fn shutdown(&self) -> bool {
+        self.0.send(true).is_ok()
     }
 }
 
 /// The receiving half of a server shutdown signal channel.
 #[derive(Debug)]
-pub struct ShutdownReceiver(Receiver<()>);
+pub struct ShutdownReceiver(watch::Receiver<bool>);
 
 impl ShutdownReceiver {
-    /// Waits to receive the shutdown signal.
+    /// Waits to receive the shutdown signal. Returns `false` if every sender
+    /// was dropped without sending the signal.
     pub async fn await_signal(&mut self) -> bool {
-        self.0.recv().await.is_some()
+        loop {
+            if *self.0.borrow_and_update() {
+                return true;
+            }
+
+            if self.0.changed().await.is_err() {
+                return false;
+            }
+        }
     }
 }
 
-/// Creates a server shutdown signal channel.
+/// Creates a server shutdown signal channel. To shut down when the process
+/// receives a termination signal, see [`Signals`](crate::signal::Signals).
 pub fn shutdown_signal() -> (ShutdownSender, ShutdownReceiver) {
-    let (tx, rx) = channel(1);
-    (ShutdownSender(tx), ShutdownReceiver(rx))
+    let (tx, rx) = watch::channel(false);
+    (ShutdownSender(Arc::new(tx)), ShutdownReceiver(rx))
+}
+
+/// A boxed error returned by a startup hook.
+type HookError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A hook run before the server starts accepting connections.
+type StartupHook = Box<
+    dyn FnOnce(ServerState) -> Pin<Box<dyn Future<Output = Result<(), HookError>> + Send>> + Send,
+>;
+
+/// A hook run after the server has drained its connections.
+type ShutdownHook = Box<dyn FnOnce(ServerState) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send>;
+
+/// Waits for a shutdown signal, or forever if there is no signal or every
+/// sender was dropped without sending it.
+async fn await_shutdown(shutdown_receiver: Option<ShutdownReceiver>) {
+    if let Some(mut shutdown_receiver) = shutdown_receiver {
+        if shutdown_receiver.await_signal().await {
+            return;
+        }
+    }
+
+    std::future::pending().await
+}
+
+/// Counts a connection as active for as long as it is alive.
+struct ConnectionGuard(Arc<AtomicUsize>);
+
+impl ConnectionGuard {
+    /// Counts a new active connection.
+    fn new(active_connections: &Arc<AtomicUsize>) -> Self {
+        active_connections.fetch_add(1, Ordering::Relaxed);
+        Self(Arc::clone(active_connections))
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A summary of a server shutdown, returned once the server stops.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ShutdownReport {
+    /// The number of connections that finished on their own after the
+    /// shutdown signal was received.
+    pub drained: usize,
+    /// The number of connections that were still open when the shutdown
+    /// timeout elapsed, and were closed forcefully.
+    pub force_closed: usize,
 }
 
 /// The sending half of an error reporting channel.
@@ -99,19 +209,63 @@ pub fn error_report_stream() -> (ErrorSender, ErrorReceiver) {
     (ErrorSender(tx), ErrorReceiver::Active(rx))
 }
 
+/// Removes a Unix socket file left behind by a server that is no longer
+/// running. Fails if the path exists but is not a socket, or if a server is
+/// still accepting connections on it.
+#[cfg(unix)]
+async fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+
+    match UnixStream::connect(path).await {
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("{} is in use by another server", path.display()),
+        )),
+        Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => std::fs::remove_file(path),
+        Err(err) => Err(err),
+    }
+}
+
 /// The internal server service managed by the `hyper` runtime.
 struct ServerService {
     /// The collection of all registered routes.
     routes: Arc<RouteLevel>,
     /// The global application state management system.
     state: StateManager,
+    /// Facts about the connection being served.
+    connection: ConnectionState,
     /// The error reporting sender, if one was configured.
     error_sender: Option<ErrorSender>,
+    /// The event reporting sender, if one was configured.
+    event_sender: Option<EventSender>,
+    /// The limit on requests handled at once, if one was configured.
+    request_limit: Option<Arc<Semaphore>>,
+    /// How long clients are told to wait before retrying rejected requests.
+    overload_retry_after: Duration,
+    /// The value of the `Server` header added to responses.
+    server_header: Option<HeaderValue>,
+    /// The maximum size of a request header section, if one was configured.
+    max_header_size: Option<usize>,
+    /// The activity of the connection, if idle connections are closed.
+    activity: Option<Arc<ConnectionActivity>>,
 }
 
 impl Service<HyperRequest<Incoming>> for ServerService {
-    type Response = HyperResponse<String>;
-    type Error = Error;
+    type Response = HyperResponse<ResponseBody>;
+    type Error = Arc<Error>;
     type Future =
         Pin<Box<dyn Future<Output = std::result::Result<Self::Response, Self::Error>> + Send>>;
 
@@ -119,32 +273,680 @@ impl Service<HyperRequest<Incoming>> for ServerService {
         let method = Method::from(req.method());
         let path = RoutePath::from(req.uri().path());
         let matched_path_and_route = self.routes.get(method, path);
+        let server_header = self.server_header.clone();
+        let events = self.event_sender.clone().map(|event_sender| {
+            let request = RequestInfo {
+                connection_id: self.connection.id(),
+                method: req.method().clone(),
+                path: req.uri().path().to_owned(),
+                route: matched_path_and_route
+                    .as_ref()
+                    .ok()
+                    .map(|(matched_path, _)| matched_path.route_path()),
+            };
+            RequestEvents::started(event_sender, request)
+        });
+
+        let permit = match &self.request_limit {
+            Some(request_limit) => match Arc::clone(request_limit).try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => {
+                    let res = Error::Overloaded(self.overload_retry_after).as_response();
+                    let res = finish_response(res, server_header, events);
+                    return Box::pin(std::future::ready(Ok(res)));
+                }
+            },
+            None => None,
+        };
+        let activity = self.activity.as_ref().map(RequestActivity::new);
+
+        if let Some(max_header_size) = self.max_header_size {
+            if header_section_size(&req) > max_header_size {
+                let res = Error::HeaderSectionTooLarge(max_header_size).as_response();
+                let res = finish_response(res, server_header, events);
+                return Box::pin(std::future::ready(Ok(res)));
+            }
+        }
+
         let state = self.state.clone();
+        let connection = self.connection.next_request();
         let error_sender = self.error_sender.clone();
 
         Box::pin(async move {
-            Ok(match matched_path_and_route {
+            let _guards = (permit, activity);
+
+            let res = match matched_path_and_route {
                 Ok((matched_path, route)) => {
-                    let req = Request::new(req, matched_path, state).await?;
-                    let res = route.call(req).await;
+                    match Request::new(req, matched_path, state, connection, route.config()).await {
+                        Ok(req) => {
+                            let res = match route.config().handler_timeout() {
+                                Some(timeout) => tokio::time::timeout(timeout, route.call(req))
+                                    .await
+                                    .unwrap_or_else(|_| {
+                                        Response::Err(Arc::new(Error::HandlerTimeout))
+                                    }),
+                                None => route.call(req).await,
+                            };
 
-                    if let Response::Err(err) = &res {
-                        if err.source().is_server() {
-                            if let Some(error_sender) = error_sender {
-                                error_sender.report(Arc::clone(err));
+                            if let Response::Err(err) = &res {
+                                if let Some(events) = &events {
+                                    events.handler_error(err);
+                                }
+
+                                if err.source().is_server() {
+                                    if let Some(error_sender) = error_sender {
+                                        error_sender.report(Arc::clone(err));
+                                    }
+                                }
                             }
+
+                            res
                         }
-                    }
+                        Err(err @ Error::ServerError(_)) => {
+                            let err = Arc::new(err);
+
+                            if let Some(events) = events {
+                                events.failed(&err);
+                            }
 
-                    res
+                            return Err(err);
+                        }
+                        Err(err) => err.as_response(),
+                    }
                 }
                 Err(err) => err.as_response(),
-            }
-            .into())
+            };
+
+            Ok(finish_response(res, server_header, events))
         })
     }
 }
 
+/// Reports the events concerning a request.
+struct RequestEvents {
+    /// The event reporting sender.
+    event_sender: EventSender,
+    /// The request.
+    request: RequestInfo,
+    /// When the request was received.
+    start: Instant,
+}
+
+impl RequestEvents {
+    /// Reports that a request was received.
+    fn started(event_sender: EventSender, request: RequestInfo) -> Self {
+        event_sender.report(ServerEvent::RequestStarted(request.clone()));
+
+        Self {
+            event_sender,
+            request,
+            start: Instant::now(),
+        }
+    }
+
+    /// Reports that the handler responded with an error.
+    fn handler_error(&self, error: &Arc<Error>) {
+        self.event_sender.report(ServerEvent::HandlerError {
+            request: self.request.clone(),
+            error: Arc::clone(error),
+        });
+    }
+
+    /// Reports that the connection failed before a response was produced.
+    fn failed(self, error: &Arc<Error>) {
+        self.event_sender.report(ServerEvent::RequestFailed {
+            request: self.request,
+            error: Arc::clone(error),
+            latency: self.start.elapsed(),
+        });
+    }
+
+    /// Reports that a response was produced.
+    fn finished(self, status: StatusCode) {
+        self.event_sender.report(ServerEvent::RequestFinished {
+            request: self.request,
+            status,
+            latency: self.start.elapsed(),
+        });
+    }
+}
+
+/// Reports that a connection was closed once dropped.
+struct ConnectionEvents {
+    /// The event reporting sender.
+    event_sender: EventSender,
+    /// The ID of the connection.
+    connection_id: u64,
+    /// When the connection was accepted.
+    start: Instant,
+}
+
+impl ConnectionEvents {
+    /// Reports that a connection was accepted.
+    fn accepted(event_sender: EventSender, connection: &ConnectionState) -> Self {
+        event_sender.report(ServerEvent::ConnectionAccepted {
+            connection_id: connection.id(),
+            remote_addr: connection.remote_addr().clone(),
+            local_addr: connection.local_addr().clone(),
+        });
+
+        Self {
+            event_sender,
+            connection_id: connection.id(),
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for ConnectionEvents {
+    fn drop(&mut self) {
+        self.event_sender.report(ServerEvent::ConnectionClosed {
+            connection_id: self.connection_id,
+            duration: self.start.elapsed(),
+        });
+    }
+}
+
+/// Gets the size of a request's header section as it is sent over HTTP/1,
+/// including the request line. `hyper` only limits the size of its read
+/// buffer, which may hold more than the configured limit after a single read,
+/// so the limit is enforced exactly here.
+fn header_section_size<B>(req: &HyperRequest<B>) -> usize {
+    let target = req
+        .uri()
+        .path_and_query()
+        .map_or(1, |path_and_query| path_and_query.as_str().len());
+    let request_line = req.method().as_str().len() + target + " HTTP/1.1\r\n".len() + 1;
+    let headers = req
+        .headers()
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len() + ": \r\n".len())
+        .sum::<usize>();
+
+    request_line + headers + "\r\n".len()
+}
+
+/// Converts a response to be sent, adding the `Server` header if it is
+/// configured and the response does not already have one, and reports that
+/// the request has finished.
+fn finish_response(
+    res: Response,
+    server_header: Option<HeaderValue>,
+    events: Option<RequestEvents>,
+) -> HyperResponse<ResponseBody> {
+    let mut res: HyperResponse<ResponseBody> = res.into();
+
+    if let Some(value) = server_header {
+        res.headers_mut().entry(SERVER).or_insert(value);
+    }
+
+    if let Some(events) = events {
+        events.finished(res.status());
+    }
+
+    res
+}
+
+/// Tracks the requests in flight on a connection, so that it can be closed
+/// once it has been idle for too long.
+struct ConnectionActivity {
+    /// How long the connection may go without any requests in flight.
+    timeout: Duration,
+    /// The number of requests in flight.
+    in_flight: AtomicUsize,
+    /// When a request last started or finished.
+    last_active: Mutex<Instant>,
+}
+
+impl ConnectionActivity {
+    /// Creates the activity of a newly opened connection.
+    fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            in_flight: AtomicUsize::new(0),
+            last_active: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Records that a request has started or finished.
+    fn touch(&self) {
+        *self.last_active.lock().unwrap() = Instant::now();
+    }
+
+    /// Waits until the connection has gone without any requests in flight
+    /// for the timeout.
+    async fn idle(&self) {
+        loop {
+            let deadline = *self.last_active.lock().unwrap() + self.timeout;
+
+            if Instant::now() < deadline {
+                tokio::time::sleep_until(deadline).await;
+            } else if self.in_flight.load(Ordering::Relaxed) == 0 {
+                return;
+            } else {
+                tokio::time::sleep(self.timeout).await;
+            }
+        }
+    }
+}
+
+/// Marks a request as in flight on a connection until dropped.
+struct RequestActivity(Arc<ConnectionActivity>);
+
+impl RequestActivity {
+    /// Marks a request as in flight.
+    fn new(activity: &Arc<ConnectionActivity>) -> Self {
+        activity.in_flight.fetch_add(1, Ordering::Relaxed);
+        activity.touch();
+        Self(Arc::clone(activity))
+    }
+}
+
+impl Drop for RequestActivity {
+    fn drop(&mut self) {
+        self.0.touch();
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// HTTP/1 protocol settings, along with the response headers added to every
+/// connection. Settings that are not configured use `hyper`'s defaults.
+#[derive(Debug, Clone, Default)]
+pub struct ServerConfig {
+    /// Whether connections are kept alive between requests.
+    keep_alive: Option<bool>,
+    /// How long a connection may go without any requests in flight.
+    idle_timeout: Option<Duration>,
+    /// The maximum number of headers in a request.
+    max_headers: Option<usize>,
+    /// The maximum size of a request header section.
+    max_header_size: Option<usize>,
+    /// Whether connections stay open after the client shuts down its half.
+    half_close: Option<bool>,
+    /// Whether responses are written using vectored writes.
+    writev: Option<bool>,
+    /// The value of the `Server` header added to responses.
+    server_header: Option<HeaderValue>,
+    /// Whether the `Date` header is added to responses.
+    date_header: Option<bool>,
+}
+
+impl ServerConfig {
+    /// Creates a new configuration using `hyper`'s defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enables or disables HTTP/1 keep-alive. When disabled, each connection
+    /// is closed after its first response. Keep-alive is enabled by default.
+    pub fn keep_alive(mut self, enabled: bool) -> Self {
+        self.keep_alive = Some(enabled);
+        self
+    }
+
+    /// Sets how long a connection may go without any requests in flight
+    /// before it is closed gracefully. HTTP/1 connections are also closed if
+    /// the headers of the next request do not arrive within the
+    /// [header read timeout](Server::with_header_read_timeout), which starts
+    /// while the connection is idle. By default, there is no limit.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum number of headers in an HTTP/1 request. Requests with
+    /// more headers are rejected with a
+    /// `431 Request Header Fields Too Large` response. Defaults to 100.
+    pub fn max_headers(mut self, max: usize) -> Self {
+        self.max_headers = Some(max);
+        self
+    }
+
+    /// Sets the maximum size of a request header section, including the
+    /// request line, in bytes. Requests with larger header sections are
+    /// rejected with a `431 Request Header Fields Too Large` response. With
+    /// HTTP/2, this also limits the size of header lists, unless
+    /// `Http2Config::max_header_list_size` is configured. Defaults to about
+    /// 400 KiB.
+    pub fn max_header_size(mut self, size: usize) -> Self {
+        self.max_header_size = Some(size);
+        self
+    }
+
+    /// Sets whether HTTP/1 connections stay open to finish sending responses
+    /// after the client shuts down its writing half. This is disabled by
+    /// default, so connections are closed as soon as the client stops
+    /// sending.
+    pub fn half_close(mut self, enabled: bool) -> Self {
+        self.half_close = Some(enabled);
+        self
+    }
+
+    /// Sets whether HTTP/1 responses are written using vectored writes,
+    /// rather than by copying them into a single buffer. By default, this is
+    /// determined by whether the connection supports vectored writes.
+    pub fn writev(mut self, enabled: bool) -> Self {
+        self.writev = Some(enabled);
+        self
+    }
+
+    /// Sets the value of the `Server` header added to responses that do not
+    /// already have one. By default, no `Server` header is added.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is not a valid header value.
+    pub fn server_header(mut self, value: &str) -> Self {
+        self.server_header =
+            Some(HeaderValue::from_str(value).expect("invalid `Server` header value"));
+        self
+    }
+
+    /// Enables or disables the `Date` header added to responses. It is
+    /// enabled by default.
+    pub fn date_header(mut self, enabled: bool) -> Self {
+        self.date_header = Some(enabled);
+        self
+    }
+
+    /// Applies the settings to a connection builder.
+    fn apply(&self, builder: &mut Builder<TokioExecutor>) {
+        let mut http1 = builder.http1();
+
+        if let Some(enabled) = self.keep_alive {
+            http1.keep_alive(enabled);
+        }
+
+        if let Some(max) = self.max_headers {
+            http1.max_headers(max);
+        }
+
+        if let Some(size) = self.max_header_size {
+            http1.max_buf_size(size.max(MIN_READ_BUFFER_SIZE));
+        }
+
+        if let Some(enabled) = self.half_close {
+            http1.half_close(enabled);
+        }
+
+        if let Some(enabled) = self.writev {
+            http1.writev(enabled);
+        }
+
+        if let Some(enabled) = self.date_header {
+            http1.auto_date_header(enabled);
+        }
+
+        #[cfg(feature = "http2")]
+        {
+            let mut http2 = builder.http2();
+
+            if let Some(size) = self.max_header_size {
+                http2.max_header_list_size(u32::try_from(size).unwrap_or(u32::MAX));
+            }
+
+            if let Some(enabled) = self.date_header {
+                http2.auto_date_header(enabled);
+            }
+        }
+    }
+}
+
+/// HTTP/2 protocol settings, applied to both HTTP/2 over TLS (negotiated via
+/// ALPN) and cleartext HTTP/2 with prior knowledge. Settings that are not
+/// configured use `hyper`'s defaults.
+#[cfg(feature = "http2")]
+#[derive(Debug, Clone, Default)]
+pub struct Http2Config {
+    /// The maximum number of concurrent streams per connection.
+    max_concurrent_streams: Option<u32>,
+    /// The initial flow control window size of each stream.
+    initial_stream_window_size: Option<u32>,
+    /// The initial flow control window size of each connection.
+    initial_connection_window_size: Option<u32>,
+    /// Whether adaptive flow control is enabled.
+    adaptive_window: Option<bool>,
+    /// The maximum frame size.
+    max_frame_size: Option<u32>,
+    /// The maximum size of a header list.
+    max_header_list_size: Option<u32>,
+    /// The interval at which keep-alive pings are sent.
+    keep_alive_interval: Option<Duration>,
+    /// How long to wait for a keep-alive ping to be acknowledged.
+    keep_alive_timeout: Option<Duration>,
+}
+
+#[cfg(feature = "http2")]
+impl Http2Config {
+    /// Creates a new HTTP/2 configuration using `hyper`'s defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of concurrent streams per connection.
+    pub fn max_concurrent_streams(mut self, max: u32) -> Self {
+        self.max_concurrent_streams = Some(max);
+        self
+    }
+
+    /// Sets the initial flow control window size of each stream, in bytes.
+    pub fn initial_stream_window_size(mut self, size: u32) -> Self {
+        self.initial_stream_window_size = Some(size);
+        self
+    }
+
+    /// Sets the initial flow control window size of each connection, in
+    /// bytes.
+    pub fn initial_connection_window_size(mut self, size: u32) -> Self {
+        self.initial_connection_window_size = Some(size);
+        self
+    }
+
+    /// Enables or disables adaptive flow control. When enabled, the
+    /// configured window sizes are ignored.
+    pub fn adaptive_window(mut self, enabled: bool) -> Self {
+        self.adaptive_window = Some(enabled);
+        self
+    }
+
+    /// Sets the maximum frame size, in bytes.
+    pub fn max_frame_size(mut self, size: u32) -> Self {
+        self.max_frame_size = Some(size);
+        self
+    }
+
+    /// Sets the maximum size of a received header list, in bytes.
+    pub fn max_header_list_size(mut self, size: u32) -> Self {
+        self.max_header_list_size = Some(size);
+        self
+    }
+
+    /// Sets the interval at which keep-alive pings are sent.
+    pub fn keep_alive_interval(mut self, interval: Duration) -> Self {
+        self.keep_alive_interval = Some(interval);
+        self
+    }
+
+    /// Sets how long to wait for a keep-alive ping to be acknowledged before
+    /// closing the connection.
+    pub fn keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.keep_alive_timeout = Some(timeout);
+        self
+    }
+
+    /// Applies the settings to a connection builder.
+    fn apply(&self, builder: &mut Builder<TokioExecutor>) {
+        let mut http2 = builder.http2();
+        http2.timer(TokioTimer::new());
+
+        if let Some(max) = self.max_concurrent_streams {
+            http2.max_concurrent_streams(max);
+        }
+
+        if let Some(size) = self.initial_stream_window_size {
+            http2.initial_stream_window_size(size);
+        }
+
+        if let Some(size) = self.initial_connection_window_size {
+            http2.initial_connection_window_size(size);
+        }
+
+        if let Some(enabled) = self.adaptive_window {
+            http2.adaptive_window(enabled);
+        }
+
+        if let Some(size) = self.max_frame_size {
+            http2.max_frame_size(size);
+        }
+
+        if let Some(size) = self.max_header_list_size {
+            http2.max_header_list_size(size);
+        }
+
+        if let Some(interval) = self.keep_alive_interval {
+            http2.keep_alive_interval(interval);
+        }
+
+        if let Some(timeout) = self.keep_alive_timeout {
+            http2.keep_alive_timeout(timeout);
+        }
+    }
+}
+
+/// Settings for the TCP sockets a server binds, applied by
+/// [`Server::serve`], [`Server::bind`] and related methods, and by
+/// [`SocketListener`]. Settings that are not configured use the operating
+/// system's defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SocketConfig {
+    /// The maximum number of pending connections.
+    backlog: Option<u32>,
+    /// Whether Nagle's algorithm is disabled on accepted connections.
+    nodelay: Option<bool>,
+    /// How long an accepted connection is idle before keepalive probes are
+    /// sent.
+    keepalive: Option<Duration>,
+}
+
+impl SocketConfig {
+    /// Creates a new socket configuration using the operating system's
+    /// defaults, with a backlog of [`DEFAULT_BACKLOG`] connections.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of connections waiting to be accepted. The
+    /// operating system may cap this. The default is [`DEFAULT_BACKLOG`].
+    pub fn backlog(mut self, backlog: u32) -> Self {
+        self.backlog = Some(backlog);
+        self
+    }
+
+    /// Sets `TCP_NODELAY` on accepted connections, which disables Nagle's
+    /// algorithm so that small writes are sent immediately.
+    pub fn nodelay(mut self, enabled: bool) -> Self {
+        self.nodelay = Some(enabled);
+        self
+    }
+
+    /// Enables TCP keepalive on accepted connections, sending probes once a
+    /// connection has been idle for the given amount of time, so that dead
+    /// peers are detected.
+    pub fn keepalive(mut self, idle: Duration) -> Self {
+        self.keepalive = Some(idle);
+        self
+    }
+
+    /// Binds a listening socket to the given address. With `reuse_port`,
+    /// `SO_REUSEPORT` is set so that other sockets can be bound to the same
+    /// address, with the operating system spreading connections across them.
+    fn bind(&self, addr: SocketAddr, reuse_port: bool) -> io::Result<std::net::TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+
+        // Matches `TcpListener::bind`, so that the address can be rebound
+        // right after the server stops.
+        #[cfg(unix)]
+        socket.set_reuse_address(true)?;
+
+        if reuse_port {
+            #[cfg(unix)]
+            socket.set_reuse_port(true)?;
+            #[cfg(not(unix))]
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "SO_REUSEPORT is not supported on this platform",
+            ));
+        }
+
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(
+            self.backlog
+                .unwrap_or(DEFAULT_BACKLOG)
+                .try_into()
+                .unwrap_or(i32::MAX),
+        )?;
+
+        Ok(socket.into())
+    }
+
+    /// Binds the given number of listening sockets to the same address,
+    /// using `SO_REUSEPORT` if there is more than one. If the address
+    /// resolves to multiple addresses, each is tried in turn until one
+    /// succeeds.
+    pub(crate) async fn bind_all<A>(
+        &self,
+        addr: A,
+        count: usize,
+    ) -> io::Result<Vec<std::net::TcpListener>>
+    where
+        A: ToSocketAddrs,
+    {
+        let count = count.max(1);
+        let mut last_err = None;
+
+        for addr in tokio::net::lookup_host(addr).await? {
+            let first = match self.bind(addr, count > 1) {
+                Ok(listener) => listener,
+                Err(err) => {
+                    last_err = Some(err);
+                    continue;
+                }
+            };
+            // When binding to port 0, the rest must use the port chosen for
+            // the first.
+            let addr = first.local_addr()?;
+            let mut listeners = vec![first];
+
+            for _ in 1..count {
+                listeners.push(self.bind(addr, true)?);
+            }
+
+            return Ok(listeners);
+        }
+
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "could not resolve to any address",
+            )
+        }))
+    }
+
+    /// Applies the settings to an accepted connection.
+    pub(crate) fn apply(&self, conn: &TcpStream) -> io::Result<()> {
+        if let Some(enabled) = self.nodelay {
+            conn.set_nodelay(enabled)?;
+        }
+
+        if let Some(idle) = self.keepalive {
+            SockRef::from(conn).set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
+        }
+
+        Ok(())
+    }
+}
+
 /// A web server. This is the core type used to configure and start a web
 /// server.
 #[derive(Default)]
@@ -157,6 +959,39 @@ pub struct Server {
     shutdown_receiver: Option<ShutdownReceiver>,
     /// The optional error reporting sender.
     error_sender: Option<ErrorSender>,
+    /// The optional event reporting sender.
+    event_sender: Option<EventSender>,
+    /// The amount of time given to connections to finish during shutdown.
+    shutdown_timeout: Option<Duration>,
+    /// The hooks run before the server starts accepting connections.
+    startup_hooks: Vec<StartupHook>,
+    /// The hooks run after the server has drained its connections.
+    shutdown_hooks: Vec<ShutdownHook>,
+    /// The HTTP/1 protocol and response header settings.
+    config: ServerConfig,
+    /// The HTTP/2 protocol settings.
+    #[cfg(feature = "http2")]
+    http2_config: Http2Config,
+    /// The permissions to set on Unix socket files created by the server.
+    #[cfg(unix)]
+    unix_socket_mode: Option<u32>,
+    /// The maximum amount of time to spend reading request headers.
+    header_read_timeout: Option<Duration>,
+    /// The proxies whose forwarding headers are trusted.
+    trusted_proxies: TrustedProxies,
+    /// The settings for TCP sockets bound by the server.
+    socket_config: SocketConfig,
+    /// The number of listening sockets bound by the server, or zero for the
+    /// default of one.
+    acceptors: usize,
+    /// Whether each accept loop runs on its own thread and runtime.
+    acceptor_threads: bool,
+    /// The maximum number of connections open at once.
+    max_connections: Option<usize>,
+    /// The maximum number of requests handled at once.
+    max_requests: Option<usize>,
+    /// How long clients are told to wait before retrying rejected requests.
+    overload_retry_after: Option<Duration>,
 }
 
 impl Server {
@@ -298,13 +1133,110 @@ impl Server {
         self
     }
 
+    /// Registers an async hook to run before the server starts accepting
+    /// connections, e.g. to warm caches or open connection pools. Hooks run
+    /// in the order they were registered, and can insert values into the
+    /// global state through the given [`ServerState`]. If a hook fails, the
+    /// remaining hooks are skipped and the server does not start, returning
+    /// the error instead.
+    pub fn on_startup<F, Fut, E>(mut self, hook: F) -> Self
+    where
+        F: FnOnce(ServerState) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Into<HookError>,
+    {
+        self.startup_hooks.push(Box::new(move |state| {
+            Box::pin(async move { hook(state).await.map_err(Into::into) })
+        }));
+        self
+    }
+
+    /// Registers an async hook to run once the server has shut down and
+    /// drained its connections, e.g. to flush buffers or close connection
+    /// pools. Hooks run in the order they were registered, with access to the
+    /// global state through the given [`ServerState`].
+    pub fn on_shutdown<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: FnOnce(ServerState) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.shutdown_hooks
+            .push(Box::new(move |state| Box::pin(hook(state))));
+        self
+    }
+
+    /// Configures the maximum size of a request body, in bytes, for all routes
+    /// that do not override it through a
+    /// [`RouteConfig`](crate::routing::RouteConfig). Requests with larger
+    /// bodies are rejected with a `413 Payload Too Large` response. Defaults
+    /// to [`DEFAULT_MAX_BODY_SIZE`](crate::routing::DEFAULT_MAX_BODY_SIZE).
+    pub fn with_max_body_size(mut self, max_body_size: u64) -> Self {
+        self.routes.config = self.routes.config.with_max_body_size(max_body_size);
+        self
+    }
+
+    /// Configures the maximum amount of time a client may take to send the
+    /// headers of an HTTP/1 request, protecting against slowloris attacks.
+    /// Connections that exceed it are closed without a response, since no
+    /// request has been received yet. Defaults to `hyper`'s default of 30
+    /// seconds.
+    pub fn with_header_read_timeout(mut self, timeout: Duration) -> Self {
+        self.header_read_timeout = Some(timeout);
+        self
+    }
+
+    /// Configures the proxies whose forwarding headers are trusted when
+    /// resolving the original client of a request through
+    /// [`ClientIp`](crate::forwarded::ClientIp) and
+    /// [`RequestOrigin`](crate::forwarded::RequestOrigin). By default, no
+    /// proxies are trusted.
+    pub fn with_trusted_proxies(mut self, trusted_proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    /// Configures the maximum amount of time to spend reading a request body,
+    /// for all routes that do not override it through a
+    /// [`RouteConfig`](crate::routing::RouteConfig). Requests whose bodies
+    /// take longer to arrive are rejected with a `408 Request Timeout`
+    /// response. By default, there is no limit.
+    pub fn with_body_read_timeout(mut self, timeout: Duration) -> Self {
+        self.routes.config = self.routes.config.with_body_read_timeout(timeout);
+        self
+    }
+
+    /// Configures the maximum amount of time a handler, including its
+    /// middleware, may take to produce a response, for all routes that do not
+    /// override it through a [`RouteConfig`](crate::routing::RouteConfig).
+    /// Handlers that take longer are cancelled, and a
+    /// `503 Service Unavailable` response is sent instead. By default, there
+    /// is no limit.
+    pub fn with_handler_timeout(mut self, timeout: Duration) -> Self {
+        self.routes.config = self.routes.config.with_handler_timeout(timeout);
+        self
+    }
+
     /// Configures a shutdown signal to enable a graceful server shutdown. See
     /// [`shutdown_signal`] for more information.
+    ///
+    /// Once the signal is received, the server stops accepting connections and
+    /// asks open connections to close once their in-flight requests have
+    /// completed. Connections still open after the shutdown timeout (see
+    /// [`with_shutdown_timeout`](Self::with_shutdown_timeout)) are closed
+    /// forcefully.
     pub fn with_graceful_shutdown(mut self, shutdown_receiver: ShutdownReceiver) -> Self {
         self.shutdown_receiver = Some(shutdown_receiver);
         self
     }
 
+    /// Configures the amount of time given to open connections to finish
+    /// during a graceful shutdown before they are closed forcefully. Defaults
+    /// to [`DEFAULT_SHUTDOWN_TIMEOUT`].
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = Some(timeout);
+        self
+    }
+
     /// Configures an error reporting stream to handle errors occurring from
     /// within route handlers. See [`error_report_stream`] for more information.
     pub fn with_error_reporting(mut self, error_sender: ErrorSender) -> Self {
@@ -312,59 +1244,751 @@ impl Server {
         self
     }
 
-    /// Starts the server running on the given address.
-    pub async fn serve<A>(self, addr: A) -> io::Result<()>
+    /// Configures an event reporting stream, describing the connections and
+    /// requests handled by the server, e.g. for monitoring. See
+    /// [`event_stream`](crate::event::event_stream) and [`ServerEvent`] for
+    /// more information.
+    pub fn with_event_reporting(mut self, event_sender: EventSender) -> Self {
+        self.event_sender = Some(event_sender);
+        self
+    }
+
+    /// Configures the HTTP/1 protocol settings and the headers added to
+    /// responses. See [`ServerConfig`] for more information.
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Configures the HTTP/2 protocol settings. See [`Http2Config`] for more
+    /// information.
+    #[cfg(feature = "http2")]
+    pub fn with_http2_config(mut self, http2_config: Http2Config) -> Self {
+        self.http2_config = http2_config;
+        self
+    }
+
+    /// Configures the permissions set on the socket file created by
+    /// [`serve_unix`](Self::serve_unix), e.g. `0o660` to allow access only to
+    /// the owning user and group. By default, the permissions are determined
+    /// by the process umask.
+    #[cfg(unix)]
+    pub fn with_unix_socket_permissions(mut self, mode: u32) -> Self {
+        self.unix_socket_mode = Some(mode);
+        self
+    }
+
+    /// Configures the TCP sockets bound by [`serve`](Self::serve),
+    /// [`bind`](Self::bind) and [`serve_tls`](Self::serve_tls), e.g. to set
+    /// the backlog or enable `TCP_NODELAY` on accepted connections.
+    pub fn with_socket_config(mut self, socket_config: SocketConfig) -> Self {
+        self.socket_config = socket_config;
+        self
+    }
+
+    /// Sets the number of listening sockets bound by [`serve`](Self::serve),
+    /// [`bind`](Self::bind) and [`serve_tls`](Self::serve_tls), each with its
+    /// own accept loop, so that a single accept loop does not become a
+    /// bottleneck on machines with many cores. With more than one, the
+    /// sockets are bound with `SO_REUSEPORT`, and the operating system
+    /// spreads incoming connections across them. All accept loops share the
+    /// same routes and state. The default is one.
+    ///
+    /// Binding more than one socket is only supported on Unix.
+    pub fn with_acceptors(mut self, count: usize) -> Self {
+        self.acceptors = count;
+        self
+    }
+
+    /// Runs each accept loop started by [`serve`](Self::serve),
+    /// [`bind`](Self::bind) or [`serve_tls`](Self::serve_tls) on its own
+    /// thread with a current-thread runtime, along with the connections it
+    /// accepts, instead of on the runtime the server was started from. Tasks
+    /// spawned by handlers run on the runtime of their connection, and are
+    /// cancelled once the server has shut down.
+    pub fn with_acceptor_threads(mut self, enabled: bool) -> Self {
+        self.acceptor_threads = enabled;
+        self
+    }
+
+    /// Limits the number of connections handed to the server and open at
+    /// once, across all accept loops. Once the limit is reached, no more
+    /// connections are accepted until one closes, so new connections wait in
+    /// the listen backlog instead of using up file descriptors. By default,
+    /// there is no limit.
+    ///
+    /// Listeners that prepare connections in the background before handing
+    /// them over, such as [`TlsListener`](crate::tls::TlsListener) and
+    /// [`ProxyProtocolListener`](crate::proxy_protocol::ProxyProtocolListener),
+    /// may hold further connections that are not counted, up to their own
+    /// limit on handshakes or headers in progress.
+    pub fn with_max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Limits the number of requests handled at once, across all
+    /// connections. Requests past the limit are rejected right away with a
+    /// `503 Service Unavailable` response and a `Retry-After` header, without
+    /// running any middleware or handlers. A request stops counting towards
+    /// the limit once its handler has responded, even if the response body
+    /// is still being sent. By default, there is no limit.
+    pub fn with_max_requests(mut self, max: usize) -> Self {
+        self.max_requests = Some(max);
+        self
+    }
+
+    /// Sets how long clients are told to wait before retrying requests
+    /// rejected by [`with_max_requests`](Self::with_max_requests). This is
+    /// rounded up to whole seconds. The default is
+    /// [`DEFAULT_OVERLOAD_RETRY_AFTER`].
+    pub fn with_overload_retry_after(mut self, retry_after: Duration) -> Self {
+        self.overload_retry_after = Some(retry_after);
+        self
+    }
+
+    /// Creates the builder used to serve HTTP on each connection.
+    fn connection_builder(&self) -> Builder<TokioExecutor> {
+        let mut builder = Builder::new(TokioExecutor::new());
+        let mut http1 = builder.http1();
+        http1.timer(TokioTimer::new());
+
+        if let Some(header_read_timeout) = self.header_read_timeout {
+            http1.header_read_timeout(header_read_timeout);
+        }
+
+        self.config.apply(&mut builder);
+
+        #[cfg(feature = "http2")]
+        self.http2_config.apply(&mut builder);
+
+        builder
+    }
+
+    /// Starts the server running on the given address. See
+    /// [`with_acceptors`](Self::with_acceptors) and
+    /// [`with_socket_config`](Self::with_socket_config) for how the address
+    /// is bound.
+    pub async fn serve<A>(self, addr: A) -> io::Result<ShutdownReport>
     where
         A: ToSocketAddrs,
     {
-        let listener = TcpListener::bind(addr).await?;
-        self.serve_with(listener).await;
+        let acceptors = self.bind_listeners(addr).await?;
+        self.serve_acceptors(acceptors).await
+    }
 
-        Ok(())
+    /// Starts the server running on the given address, terminating TLS on
+    /// every accepted connection using the given configuration.
+    #[cfg(feature = "tls")]
+    pub async fn serve_tls<A>(self, addr: A, tls_config: TlsConfig) -> io::Result<ShutdownReport>
+    where
+        A: ToSocketAddrs,
+    {
+        let acceptors = self
+            .bind_listeners(addr)
+            .await?
+            .into_iter()
+            .map(|acceptor| Acceptor {
+                listener: TlsListener::new(acceptor.listener, tls_config.clone()),
+                runtime: acceptor.runtime,
+            })
+            .collect();
+        self.serve_acceptors(acceptors).await
+    }
+
+    /// Starts the server running on the given listener, terminating TLS on
+    /// every accepted connection using the given configuration. This is
+    /// shorthand for `.serve_with(TlsListener::new(listener, tls_config))`.
+    /// Reloading the configuration while the server is running applies to all
+    /// connections accepted afterwards.
+    #[cfg(feature = "tls")]
+    pub async fn serve_tls_with<L>(
+        self,
+        listener: L,
+        tls_config: TlsConfig,
+    ) -> io::Result<ShutdownReport>
+    where
+        L: Listener,
+    {
+        self.serve_with(TlsListener::new(listener, tls_config))
+            .await
+    }
+
+    /// Starts the server running on a Unix domain socket at the given path. A
+    /// stale socket file left behind by a previous server is removed first,
+    /// but the server refuses to start if another server is still accepting
+    /// connections on the path, or if the path exists and is not a socket.
+    /// The socket file is removed again once the server shuts down.
+    ///
+    /// Clients' credentials can be extracted from requests as
+    /// [`PeerCredentials`](crate::connection::PeerCredentials).
+    #[cfg(unix)]
+    pub async fn serve_unix<P>(self, path: P) -> io::Result<ShutdownReport>
+    where
+        P: AsRef<Path>,
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = path.as_ref();
+        remove_stale_socket(path).await?;
+        let listener = UnixListener::bind(path)?;
+
+        if let Some(mode) = self.unix_socket_mode {
+            if let Err(err) = std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            {
+                _ = std::fs::remove_file(path);
+                return Err(err);
+            }
+        }
+
+        let report = self.serve_unix_with(listener).await;
+        _ = std::fs::remove_file(path);
+
+        report
+    }
+
+    /// Starts the server running on the given Unix domain socket listener.
+    ///
+    /// Clients' credentials can be extracted from requests as
+    /// [`PeerCredentials`](crate::connection::PeerCredentials).
+    #[cfg(unix)]
+    pub async fn serve_unix_with(self, listener: UnixListener) -> io::Result<ShutdownReport> {
+        self.serve_with(listener).await
+    }
+
+    /// Starts the server running on the first listening socket passed to the
+    /// process using socket activation, e.g. by a systemd socket unit or by a
+    /// previous instance of the server through a
+    /// [`ListenerHandoff`](crate::activation::ListenerHandoff). This fails if
+    /// no socket was passed. Use
+    /// [`InheritedListeners`](crate::activation::InheritedListeners) to pick
+    /// sockets by name, to serve more than one, or to remove the socket
+    /// activation variables from the environment.
+    #[cfg(all(unix, feature = "socket-activation"))]
+    pub async fn serve_inherited(self) -> io::Result<ShutdownReport> {
+        let listener = InheritedListeners::from_env().take(0)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "no listening socket was passed to the process",
+            )
+        })?;
+
+        self.serve_with(listener).await
+    }
+
+    /// Starts the server running on an open listening socket, either a TCP
+    /// or a Unix domain socket. See
+    /// [`InheritedListener::from_fd`](crate::activation::InheritedListener::from_fd)
+    /// for more information.
+    #[cfg(all(unix, feature = "socket-activation"))]
+    pub async fn serve_fd(self, fd: OwnedFd) -> io::Result<ShutdownReport> {
+        self.serve_with(InheritedListener::from_fd(fd)?).await
+    }
+
+    /// Starts the server running on the given listener. This accepts a
+    /// `TcpListener`, a `UnixListener`, or any other [`Listener`]
+    /// implementation, such as an in-memory
+    /// [`MemoryListener`](crate::listener::MemoryListener).
+    ///
+    /// The server runs until the configured shutdown signal is received, then
+    /// drains open connections before returning. See
+    /// [`with_graceful_shutdown`](Self::with_graceful_shutdown) for more
+    /// information. Startup hooks are run before accepting connections, and
+    /// their failure is returned as an error.
+    pub async fn serve_with<L>(self, listener: L) -> io::Result<ShutdownReport>
+    where
+        L: Listener,
+    {
+        self.serve_acceptors(vec![Acceptor::new(listener)]).await
+    }
+
+    /// Starts the server running in the background on the given address,
+    /// returning a handle to the running server once the address is bound.
+    /// See [`with_acceptors`](Self::with_acceptors) and
+    /// [`with_socket_config`](Self::with_socket_config) for how the address
+    /// is bound.
+    pub async fn bind<A>(self, addr: A) -> io::Result<RunningServer>
+    where
+        A: ToSocketAddrs,
+    {
+        let acceptors = self.bind_listeners(addr).await?;
+        self.bind_acceptors(acceptors).await
     }
 
-    /// Starts the server running on the given TCP listener.
-    pub async fn serve_with(self, listener: TcpListener) {
-        let routes = Arc::new(self.routes.into_route_level());
+    /// Starts the server running in the background on the given listener,
+    /// returning a handle to the running server once the startup hooks have
+    /// completed. If a startup hook fails, its error is returned instead.
+    ///
+    /// The server can be shut down through the handle, in addition to any
+    /// shutdown signal configured with
+    /// [`with_graceful_shutdown`](Self::with_graceful_shutdown).
+    pub async fn bind_with<L>(self, listener: L) -> io::Result<RunningServer>
+    where
+        L: Listener,
+    {
+        self.bind_acceptors(vec![Acceptor::new(listener)]).await
+    }
+
+    /// Binds the configured number of listeners to the given address, along
+    /// with their dedicated runtimes if configured.
+    async fn bind_listeners<A>(&self, addr: A) -> io::Result<Vec<Acceptor<SocketListener>>>
+    where
+        A: ToSocketAddrs,
+    {
+        let listeners = self.socket_config.bind_all(addr, self.acceptors).await?;
+
+        listeners
+            .into_iter()
+            .map(|listener| {
+                let runtime = if self.acceptor_threads {
+                    Some(
+                        tokio::runtime::Builder::new_current_thread()
+                            .enable_all()
+                            .build()?,
+                    )
+                } else {
+                    None
+                };
+
+                // The listener is registered with the runtime it will be
+                // accepted on.
+                let listener = {
+                    let _guard = runtime.as_ref().map(Runtime::enter);
+                    SocketListener::from_std(listener, self.socket_config)?
+                };
+
+                Ok(Acceptor { listener, runtime })
+            })
+            .collect()
+    }
+
+    /// Starts the server running on the given acceptors.
+    async fn serve_acceptors<L>(mut self, acceptors: Vec<Acceptor<L>>) -> io::Result<ShutdownReport>
+    where
+        L: Listener,
+    {
+        self.run_startup_hooks().await?;
+        Ok(self.run(acceptors, Arc::default(), None).await)
+    }
+
+    /// Starts the server running in the background on the given acceptors.
+    async fn bind_acceptors<L>(mut self, acceptors: Vec<Acceptor<L>>) -> io::Result<RunningServer>
+    where
+        L: Listener,
+    {
+        let local_addr = acceptors[0].listener.local_addr()?;
+        self.run_startup_hooks().await?;
+
+        let (shutdown_sender, shutdown_receiver) = shutdown_signal();
+        let active_connections = Arc::<AtomicUsize>::default();
+        let serve_task = tokio::spawn(self.run(
+            acceptors,
+            Arc::clone(&active_connections),
+            Some(shutdown_receiver),
+        ));
+
+        Ok(RunningServer {
+            local_addr,
+            shutdown_sender,
+            active_connections,
+            serve_task,
+        })
+    }
+
+    /// Runs the startup hooks in order, stopping at the first failure.
+    async fn run_startup_hooks(&mut self) -> io::Result<()> {
+        let state = ServerState::startup(std::mem::take(&mut self.state));
+        let mut res = Ok(());
+
+        for hook in std::mem::take(&mut self.startup_hooks) {
+            if let Err(err) = hook(state.clone()).await {
+                res = Err(io::Error::other(err));
+                break;
+            }
+        }
+
+        self.state = state.take();
+        res
+    }
+
+    /// Runs the server on the given acceptors until a shutdown signal is
+    /// received through either the configured receiver or the given one, then
+    /// drains open connections.
+    async fn run<L>(
+        self,
+        acceptors: Vec<Acceptor<L>>,
+        active_connections: Arc<AtomicUsize>,
+        handle_shutdown_receiver: Option<ShutdownReceiver>,
+    ) -> ShutdownReport
+    where
+        L: Listener,
+    {
+        let builder = self.connection_builder();
         let state = Arc::new(self.state);
-        let mut shutdown_receiver = self
-            .shutdown_receiver
-            .unwrap_or_else(|| shutdown_signal().1);
+        let context = Arc::new(AcceptContext {
+            builder,
+            routes: Arc::new(self.routes.into_route_level()),
+            state: Arc::clone(&state),
+            trusted_proxies: Arc::new(self.trusted_proxies),
+            error_sender: self.error_sender.clone(),
+            event_sender: self.event_sender.clone(),
+            active_connections,
+            shutdown_timeout: self.shutdown_timeout.unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT),
+            connection_limit: self
+                .max_connections
+                .map(|max| Arc::new(Semaphore::new(max.min(Semaphore::MAX_PERMITS)))),
+            request_limit: self
+                .max_requests
+                .map(|max| Arc::new(Semaphore::new(max.min(Semaphore::MAX_PERMITS)))),
+            overload_retry_after: self
+                .overload_retry_after
+                .unwrap_or(DEFAULT_OVERLOAD_RETRY_AFTER),
+            idle_timeout: self.config.idle_timeout,
+            server_header: self.config.server_header.clone(),
+            max_header_size: self.config.max_header_size,
+        });
+        let (stop_sender, stop_receiver) = watch::channel(false);
+        let mut accept_loops = JoinSet::new();
 
-        loop {
-            let conn = tokio::select! {
-                conn = listener.accept() => {
-                    match conn {
-                        Ok((conn, _)) => conn,
-                        Err(_) => continue,
-                    }
+        for acceptor in acceptors {
+            let accept_loop = accept_loop(
+                Arc::clone(&context),
+                acceptor.listener,
+                stop_receiver.clone(),
+            );
+
+            match acceptor.runtime {
+                None => {
+                    accept_loops.spawn(accept_loop);
                 }
-                shutdown = shutdown_receiver.await_signal() => {
-                    if shutdown {
-                        break;
-                    } else {
-                        continue;
-                    }
+                Some(runtime) => {
+                    let (report_sender, report_receiver) = oneshot::channel();
+                    let thread = std::thread::spawn(move || {
+                        _ = report_sender.send(runtime.block_on(accept_loop));
+                    });
+
+                    accept_loops.spawn(async move {
+                        match report_receiver.await {
+                            Ok(report) => report,
+                            Err(_) => match tokio::task::spawn_blocking(|| thread.join()).await {
+                                Ok(Err(panic)) => std::panic::resume_unwind(panic),
+                                _ => ShutdownReport::default(),
+                            },
+                        }
+                    });
                 }
-            };
+            }
+        }
 
-            let conn = TokioIo::new(conn);
+        drop(context);
 
-            let hyper_service = ServerService {
-                routes: Arc::clone(&routes),
-                state: StateManager(Arc::clone(&state)),
-                error_sender: self.error_sender.clone(),
-            };
+        tokio::select! {
+            () = await_shutdown(self.shutdown_receiver) => {}
+            () = await_shutdown(handle_shutdown_receiver) => {}
+        }
 
-            tokio::spawn(async move {
-                _ = Builder::new(TokioExecutor::new())
-                    .serve_connection(conn, hyper_service)
-                    .await;
-            });
+        stop_sender.send_replace(true);
+        let mut report = ShutdownReport::default();
+
+        while let Some(res) = accept_loops.join_next().await {
+            match res {
+                Ok(accept_loop_report) => {
+                    report.drained += accept_loop_report.drained;
+                    report.force_closed += accept_loop_report.force_closed;
+                }
+                Err(err) => std::panic::resume_unwind(err.into_panic()),
+            }
+        }
+
+        let hook_state = ServerState::shutdown(state);
+
+        for hook in self.shutdown_hooks {
+            hook(hook_state.clone()).await;
         }
 
         if let Some(error_sender) = self.error_sender {
             error_sender.close();
         }
+
+        if let Some(event_sender) = self.event_sender {
+            event_sender.close();
+        }
+
+        report
+    }
+}
+
+/// A listener, along with the dedicated runtime its accept loop and
+/// connections run on, if it has one.
+struct Acceptor<L> {
+    /// The listener.
+    listener: L,
+    /// The dedicated runtime, if any.
+    runtime: Option<Runtime>,
+}
+
+impl<L> Acceptor<L> {
+    /// Creates an acceptor that runs on the current runtime.
+    fn new(listener: L) -> Self {
+        Self {
+            listener,
+            runtime: None,
+        }
+    }
+}
+
+/// The parts of a running server shared by all of its accept loops.
+struct AcceptContext {
+    /// The builder used to serve HTTP on each connection.
+    builder: Builder<TokioExecutor>,
+    /// The compiled routes.
+    routes: Arc<RouteLevel>,
+    /// The global application state.
+    state: Arc<TypeMap>,
+    /// The proxies whose forwarding headers are trusted.
+    trusted_proxies: Arc<TrustedProxies>,
+    /// The error reporting sender, if one was configured.
+    error_sender: Option<ErrorSender>,
+    /// The event reporting sender, if one was configured.
+    event_sender: Option<EventSender>,
+    /// The number of currently open connections across all accept loops.
+    active_connections: Arc<AtomicUsize>,
+    /// The amount of time given to connections to finish during shutdown.
+    shutdown_timeout: Duration,
+    /// The limit on connections open at once, if one was configured.
+    connection_limit: Option<Arc<Semaphore>>,
+    /// The limit on requests handled at once, if one was configured.
+    request_limit: Option<Arc<Semaphore>>,
+    /// How long clients are told to wait before retrying rejected requests.
+    overload_retry_after: Duration,
+    /// How long a connection may go without any requests in flight.
+    idle_timeout: Option<Duration>,
+    /// The value of the `Server` header added to responses.
+    server_header: Option<HeaderValue>,
+    /// The maximum size of a request header section, if one was configured.
+    max_header_size: Option<usize>,
+}
+
+/// Backs off from accepting connections after a listener fails, e.g. because
+/// the process has run out of file descriptors, so that the accept loop does
+/// not spin while the failure persists.
+#[derive(Debug, Default)]
+struct AcceptBackoff {
+    /// The current delay, if the last attempt failed.
+    delay: Option<Duration>,
+    /// When accepting may resume.
+    resume_at: Option<Instant>,
+}
+
+impl AcceptBackoff {
+    /// Waits until accepting may resume.
+    async fn wait(&mut self) {
+        if let Some(resume_at) = self.resume_at {
+            tokio::time::sleep_until(resume_at).await;
+            self.resume_at = None;
+        }
+    }
+
+    /// Records a failure, doubling the delay up to a limit.
+    fn failed(&mut self) {
+        let delay = self.delay.map_or(MIN_ACCEPT_BACKOFF, |delay| {
+            (delay * 2).min(MAX_ACCEPT_BACKOFF)
+        });
+        self.delay = Some(delay);
+        self.resume_at = Some(Instant::now() + delay);
+    }
+
+    /// Records a success, resetting the delay.
+    fn succeeded(&mut self) {
+        self.delay = None;
+    }
+}
+
+/// Accepts the next connection, waiting for room under the connection limit
+/// first. Listener failures that do not affect only a single connection are
+/// reported and backed off from.
+async fn accept_next<L>(
+    context: &AcceptContext,
+    listener: &mut L,
+    backoff: &mut AcceptBackoff,
+) -> (L::Io, PeerInfo, Option<OwnedSemaphorePermit>)
+where
+    L: Listener,
+{
+    loop {
+        backoff.wait().await;
+
+        let permit = match &context.connection_limit {
+            Some(connection_limit) => Arc::clone(connection_limit).acquire_owned().await.ok(),
+            None => None,
+        };
+
+        match listener.accept().await {
+            Ok((conn, peer)) => {
+                backoff.succeeded();
+                return (conn, peer, permit);
+            }
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::ConnectionAborted
+                        | io::ErrorKind::ConnectionReset
+                        | io::ErrorKind::ConnectionRefused
+                ) => {}
+            Err(err) => {
+                backoff.failed();
+                let err = Arc::new(Error::AcceptError(err));
+
+                if let Some(event_sender) = &context.event_sender {
+                    event_sender.report(ServerEvent::AcceptFailed(Arc::clone(&err)));
+                }
+
+                if let Some(error_sender) = &context.error_sender {
+                    error_sender.report(err);
+                }
+            }
+        }
+    }
+}
+
+/// Accepts connections on a listener until the server is stopped, then
+/// drains the connections it accepted.
+async fn accept_loop<L>(
+    context: Arc<AcceptContext>,
+    mut listener: L,
+    mut stop_receiver: watch::Receiver<bool>,
+) -> ShutdownReport
+where
+    L: Listener,
+{
+    let (drain_sender, drain_receiver) = watch::channel(());
+    let open_connections = Arc::<AtomicUsize>::default();
+    let mut connections = JoinSet::new();
+    let mut backoff = AcceptBackoff::default();
+
+    loop {
+        let (conn, peer, permit) = tokio::select! {
+            conn = accept_next(&context, &mut listener, &mut backoff) => conn,
+            Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+            _ = stop_receiver.wait_for(|stopped| *stopped) => break,
+        };
+
+        let conn = TokioIo::new(conn);
+        let builder = context.builder.clone();
+        let mut drain_receiver = drain_receiver.clone();
+        let activity = context
+            .idle_timeout
+            .map(|timeout| Arc::new(ConnectionActivity::new(timeout)));
+        let connection = ConnectionState::new(peer, Arc::clone(&context.trusted_proxies));
+        let guards = (
+            ConnectionGuard::new(&context.active_connections),
+            ConnectionGuard::new(&open_connections),
+            permit,
+            context
+                .event_sender
+                .clone()
+                .map(|event_sender| ConnectionEvents::accepted(event_sender, &connection)),
+        );
+
+        let hyper_service = ServerService {
+            routes: Arc::clone(&context.routes),
+            state: StateManager(Arc::clone(&context.state)),
+            connection,
+            error_sender: context.error_sender.clone(),
+            event_sender: context.event_sender.clone(),
+            request_limit: context.request_limit.clone(),
+            overload_retry_after: context.overload_retry_after,
+            server_header: context.server_header.clone(),
+            max_header_size: context.max_header_size,
+            activity: activity.clone(),
+        };
+
+        connections.spawn(async move {
+            let _guards = guards;
+            let conn = builder.serve_connection_with_upgrades(conn, hyper_service);
+            tokio::pin!(conn);
+
+            let idle = async {
+                match &activity {
+                    Some(activity) => activity.idle().await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                _ = conn.as_mut() => return,
+                _ = drain_receiver.changed() => {}
+                () = idle => {}
+            }
+
+            conn.as_mut().graceful_shutdown();
+            _ = conn.await;
+        });
+    }
+
+    drop(listener);
+    drain_sender.send_replace(());
+
+    let open = open_connections.load(Ordering::Relaxed);
+    let drain = async { while connections.join_next().await.is_some() {} };
+    _ = tokio::time::timeout(context.shutdown_timeout, drain).await;
+
+    // Both numbers come from the same counter, since finished connections
+    // may still be waiting in the set to be joined.
+    let force_closed = open_connections.load(Ordering::Relaxed);
+    connections.shutdown().await;
+
+    ShutdownReport {
+        drained: open.saturating_sub(force_closed),
+        force_closed,
+    }
+}
+
+/// A handle to a server running in the background, created by
+/// [`Server::bind`] or [`Server::bind_with`]. Dropping the handle leaves the
+/// server running.
+#[derive(Debug)]
+pub struct RunningServer {
+    /// The local address the server is bound to.
+    local_addr: Addr,
+    /// The sender used to shut down the server.
+    shutdown_sender: ShutdownSender,
+    /// The number of currently open connections.
+    active_connections: Arc<AtomicUsize>,
+    /// The task running the server.
+    serve_task: JoinHandle<ShutdownReport>,
+}
+
+impl RunningServer {
+    /// Gets the local address the server is bound to. When binding to port 0,
+    /// this contains the port chosen by the operating system.
+    pub fn local_addr(&self) -> &Addr {
+        &self.local_addr
+    }
+
+    /// Gets the number of currently open connections.
+    pub fn connections(&self) -> usize {
+        self.active_connections.load(Ordering::Relaxed)
+    }
+
+    /// Gets a sender that can be used to shut down the server, e.g. with
+    /// [`Signals`](crate::signal::Signals).
+    pub fn shutdown_sender(&self) -> ShutdownSender {
+        self.shutdown_sender.clone()
+    }
+
+    /// Shuts down the server gracefully, waiting for it to stop.
+    pub async fn shutdown(self) -> ShutdownReport {
+        self.shutdown_sender.shutdown().await;
+        self.join().await
+    }
+
+    /// Waits for the server to stop, e.g. after a shutdown signal is received.
+    /// If the server panicked, the panic is resumed.
+    pub async fn join(self) -> ShutdownReport {
+        match self.serve_task.await {
+            Ok(report) => report,
+            Err(err) => std::panic::resume_unwind(err.into_panic()),
+        }
     }
 }
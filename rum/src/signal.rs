@@ -0,0 +1,191 @@
+//! Operating system signal handling.
+
+use crate::server::ShutdownSender;
+use std::fmt::Debug;
+use std::io;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::task::JoinHandle;
+
+/// The exit code used when a second shutdown signal forces the process to
+/// exit.
+const FORCED_EXIT_CODE: i32 = 130;
+
+/// A hook run when the process receives a reload signal.
+type ReloadHook = Box<dyn Fn() + Send + Sync>;
+
+/// Handles operating system signals on behalf of one or more servers. `SIGINT`
+/// (e.g. Ctrl-C) and `SIGTERM` trigger a graceful shutdown through every
+/// configured [`ShutdownSender`], and `SIGHUP` runs every configured reload
+/// hook, e.g. to reload a [`TlsConfig`](crate::tls::TlsConfig). Signals are
+/// only intercepted if something is configured to handle them. On platforms
+/// other than Unix, only Ctrl-C is handled.
+///
+/// Once a shutdown has been triggered, a second shutdown signal exits the
+/// process immediately, as it would if the signal were not intercepted. This
+/// can be disabled with
+/// [`with_force_exit_on_repeat`](Self::with_force_exit_on_repeat), in which
+/// case further shutdown signals are ignored.
+pub struct Signals {
+    /// The senders used to trigger a shutdown.
+    shutdown_senders: Vec<ShutdownSender>,
+    /// The hooks run on a reload signal.
+    reload_hooks: Vec<ReloadHook>,
+    /// Whether a second shutdown signal exits the process immediately.
+    force_exit_on_repeat: bool,
+}
+
+impl Signals {
+    /// Creates a new signal handler with nothing configured.
+    pub fn new() -> Self {
+        Self {
+            shutdown_senders: Vec::new(),
+            reload_hooks: Vec::new(),
+            force_exit_on_repeat: true,
+        }
+    }
+
+    /// Triggers a shutdown through the given sender when a shutdown signal is
+    /// received. This can be called multiple times to shut down multiple
+    /// servers.
+    pub fn with_shutdown(mut self, shutdown_sender: ShutdownSender) -> Self {
+        self.shutdown_senders.push(shutdown_sender);
+        self
+    }
+
+    /// Runs the given hook when a reload signal is received. Hooks are run in
+    /// the order they were registered.
+    pub fn on_reload<F>(mut self, hook: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.reload_hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Sets whether a second `SIGINT` or `SIGTERM`, received after a shutdown
+    /// has been triggered, exits the process immediately, e.g. when draining
+    /// connections takes too long. This skips draining, shutdown hooks and
+    /// destructors. It is enabled by default.
+    pub fn with_force_exit_on_repeat(mut self, enabled: bool) -> Self {
+        self.force_exit_on_repeat = enabled;
+        self
+    }
+
+    /// Starts listening for signals in a background task. The signal handlers
+    /// are registered before this returns, so signals received afterwards are
+    /// never missed. This must be called from within a Tokio runtime.
+    #[cfg(unix)]
+    pub fn listen(self) -> io::Result<JoinHandle<()>> {
+        let (mut interrupt, mut terminate) = if self.shutdown_senders.is_empty() {
+            (None, None)
+        } else {
+            (
+                Some(signal(SignalKind::interrupt())?),
+                Some(signal(SignalKind::terminate())?),
+            )
+        };
+        let mut hangup = if self.reload_hooks.is_empty() {
+            None
+        } else {
+            Some(signal(SignalKind::hangup())?)
+        };
+
+        Ok(tokio::spawn(async move {
+            let mut shutting_down = false;
+
+            loop {
+                tokio::select! {
+                    _ = recv(&mut interrupt) => {}
+                    _ = recv(&mut terminate) => {}
+                    _ = recv(&mut hangup) => {
+                        self.reload();
+                        continue;
+                    }
+                }
+
+                if shutting_down {
+                    self.repeated_shutdown();
+                    continue;
+                }
+
+                shutting_down = true;
+                self.shutdown().await;
+            }
+        }))
+    }
+
+    /// Starts listening for signals in a background task. This must be called
+    /// from within a Tokio runtime.
+    #[cfg(not(unix))]
+    pub fn listen(self) -> io::Result<JoinHandle<()>> {
+        Ok(tokio::spawn(async move {
+            if self.shutdown_senders.is_empty() {
+                return;
+            }
+
+            let mut shutting_down = false;
+
+            while tokio::signal::ctrl_c().await.is_ok() {
+                if shutting_down {
+                    self.repeated_shutdown();
+                    continue;
+                }
+
+                shutting_down = true;
+                self.shutdown().await;
+            }
+        }))
+    }
+
+    /// Triggers a shutdown through every configured sender.
+    async fn shutdown(&self) {
+        for shutdown_sender in &self.shutdown_senders {
+            shutdown_sender.shutdown().await;
+        }
+    }
+
+    /// Handles a shutdown signal received after a shutdown was triggered.
+    fn repeated_shutdown(&self) {
+        if self.force_exit_on_repeat {
+            std::process::exit(FORCED_EXIT_CODE);
+        }
+    }
+
+    /// Runs every configured reload hook.
+    #[cfg_attr(not(unix), allow(dead_code))]
+    fn reload(&self) {
+        for hook in &self.reload_hooks {
+            hook();
+        }
+    }
+}
+
+impl Default for Signals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for Signals {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Signals")
+            .field("shutdown_senders", &self.shutdown_senders)
+            .field("reload_hooks", &self.reload_hooks.len())
+            .field("force_exit_on_repeat", &self.force_exit_on_repeat)
+            .finish()
+    }
+}
+
+/// Waits for the next delivery of a signal, or forever if the signal is not
+/// being handled.
+#[cfg(unix)]
+async fn recv(signal: &mut Option<Signal>) {
+    if let Some(signal) = signal {
+        if signal.recv().await.is_some() {
+            return;
+        }
+    }
+
+    std::future::pending().await
+}
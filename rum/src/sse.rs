@@ -0,0 +1,306 @@
+//! Server-Sent Events responses.
+
+use crate::body::{BoxError, Bytes};
+use crate::error::{Error, Result};
+use futures_core::Stream;
+use serde::Serialize;
+use std::borrow::{Borrow, BorrowMut};
+use std::fmt::{Debug, Write};
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{sleep, Instant, Sleep};
+
+/// The default interval at which keep-alive comments are sent while no events
+/// are being produced.
+pub const DEFAULT_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The comment sent to keep an idle event stream alive.
+const KEEP_ALIVE_COMMENT: &[u8] = b":\n\n";
+
+/// A boxed stream of events.
+type BoxEventStream = Pin<Box<dyn Stream<Item = std::result::Result<Event, BoxError>> + Send>>;
+
+/// A single Server-Sent Event. Every field is optional, although an event
+/// without data is not dispatched by browsers.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Event {
+    /// The event ID.
+    id: Option<String>,
+    /// The event name.
+    event: Option<String>,
+    /// The event data.
+    data: Option<String>,
+    /// The client reconnection time.
+    retry: Option<Duration>,
+    /// A comment, which is ignored by clients.
+    comment: Option<String>,
+}
+
+impl Event {
+    /// Creates a new event with no fields set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the event ID, which the client sends back in the `Last-Event-ID`
+    /// header when it reconnects. Line breaks are removed.
+    pub fn with_id<S>(mut self, id: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.id = Some(single_line(id.into()));
+        self
+    }
+
+    /// Sets the event name, which the client uses to dispatch the event. Line
+    /// breaks are removed.
+    pub fn with_event<S>(mut self, event: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.event = Some(single_line(event.into()));
+        self
+    }
+
+    /// Sets the event data. Data spanning multiple lines is sent as multiple
+    /// `data` fields, which the client joins back together.
+    pub fn with_data<S>(mut self, data: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.data = Some(data.into());
+        self
+    }
+
+    /// Sets the event data to the JSON representation of a value.
+    pub fn with_json_data<T>(mut self, data: &T) -> Result<Self>
+    where
+        T: Serialize,
+    {
+        self.data = Some(serde_json::to_string(data).map_err(Error::ServerJsonError)?);
+        Ok(self)
+    }
+
+    /// Sets the time the client waits before reconnecting after the stream is
+    /// interrupted.
+    pub fn with_retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Sets a comment, which is ignored by clients.
+    pub fn with_comment<S>(mut self, comment: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Serializes the event in the `text/event-stream` format.
+    fn to_bytes(&self) -> Bytes {
+        let mut buf = String::new();
+
+        if let Some(comment) = &self.comment {
+            write_lines(&mut buf, "", comment);
+        }
+
+        if let Some(id) = &self.id {
+            writeln!(buf, "id: {}", id).unwrap();
+        }
+
+        if let Some(event) = &self.event {
+            writeln!(buf, "event: {}", event).unwrap();
+        }
+
+        if let Some(retry) = self.retry {
+            writeln!(buf, "retry: {}", retry.as_millis()).unwrap();
+        }
+
+        if let Some(data) = &self.data {
+            write_lines(&mut buf, "data", data);
+        }
+
+        buf.push('\n');
+        buf.into()
+    }
+}
+
+/// Removes line breaks from a field value.
+fn single_line(value: String) -> String {
+    if value.contains(['\r', '\n']) {
+        value.replace(['\r', '\n'], "")
+    } else {
+        value
+    }
+}
+
+/// Writes a field value as one field per line.
+fn write_lines(buf: &mut String, field: &str, value: &str) {
+    for line in value
+        .split("\r\n")
+        .flat_map(|line| line.split(['\r', '\n']))
+    {
+        writeln!(buf, "{}: {}", field, line).unwrap();
+    }
+}
+
+/// A Server-Sent Events response, built from a stream of events. The events
+/// are sent to the client as they are produced, and a keep-alive comment is
+/// sent whenever no event has been produced for a while, so that proxies do
+/// not close the connection. The response ends when the stream ends, and is
+/// aborted if the stream produces an error.
+pub struct Sse {
+    /// The stream of events.
+    events: BoxEventStream,
+    /// The keep-alive interval.
+    keep_alive: Option<Duration>,
+}
+
+impl Sse {
+    /// Creates a new Server-Sent Events response from a stream of events.
+    pub fn new<S, E>(events: S) -> Self
+    where
+        S: Stream<Item = std::result::Result<Event, E>> + Send + 'static,
+        E: Into<BoxError>,
+    {
+        Self {
+            events: Box::pin(MapErr(Box::pin(events))),
+            keep_alive: Some(DEFAULT_KEEP_ALIVE_INTERVAL),
+        }
+    }
+
+    /// Sets the interval at which keep-alive comments are sent while no events
+    /// are being produced. The default is
+    /// [`DEFAULT_KEEP_ALIVE_INTERVAL`].
+    pub fn with_keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = Some(interval);
+        self
+    }
+
+    /// Disables keep-alive comments.
+    pub fn without_keep_alive(mut self) -> Self {
+        self.keep_alive = None;
+        self
+    }
+
+    /// Converts the events into a stream of serialized response body chunks.
+    pub(crate) fn into_stream(self) -> SseStream {
+        SseStream {
+            events: self.events,
+            keep_alive: self.keep_alive,
+            timer: None,
+        }
+    }
+}
+
+impl Debug for Sse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sse")
+            .field("keep_alive", &self.keep_alive)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Adapts the error type of a stream of events.
+struct MapErr<S>(Pin<Box<S>>);
+
+impl<S, E> Stream for MapErr<S>
+where
+    S: Stream<Item = std::result::Result<Event, E>>,
+    E: Into<BoxError>,
+{
+    type Item = std::result::Result<Event, BoxError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0
+            .as_mut()
+            .poll_next(cx)
+            .map(|item| item.map(|res| res.map_err(Into::into)))
+    }
+}
+
+/// The serialized body of a Server-Sent Events response.
+pub(crate) struct SseStream {
+    /// The stream of events.
+    events: BoxEventStream,
+    /// The keep-alive interval.
+    keep_alive: Option<Duration>,
+    /// The keep-alive timer. This is created on first poll, since timers can
+    /// only be created within a Tokio runtime.
+    timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl Stream for SseStream {
+    type Item = std::result::Result<Bytes, BoxError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        match this.events.as_mut().poll_next(cx) {
+            Poll::Ready(Some(Ok(event))) => {
+                if let (Some(interval), Some(timer)) = (this.keep_alive, &mut this.timer) {
+                    timer.as_mut().reset(Instant::now() + interval);
+                }
+
+                return Poll::Ready(Some(Ok(event.to_bytes())));
+            }
+            Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => {}
+        }
+
+        if let Some(interval) = this.keep_alive {
+            let timer = this.timer.get_or_insert_with(|| Box::pin(sleep(interval)));
+
+            if timer.as_mut().poll(cx).is_ready() {
+                timer.as_mut().reset(Instant::now() + interval);
+                return Poll::Ready(Some(Ok(Bytes::from_static(KEEP_ALIVE_COMMENT))));
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+/// The value of the `Last-Event-ID` request header, sent by clients
+/// reconnecting to an event stream. This is `None` if the header is not
+/// present, e.g. on the first connection.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct LastEventId(pub(crate) Option<String>);
+
+impl LastEventId {
+    /// Moves the event ID out of this wrapper.
+    pub fn into_inner(self) -> Option<String> {
+        self.0
+    }
+}
+
+impl Deref for LastEventId {
+    type Target = Option<String>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for LastEventId {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Borrow<Option<String>> for LastEventId {
+    fn borrow(&self) -> &Option<String> {
+        &self.0
+    }
+}
+
+impl BorrowMut<Option<String>> for LastEventId {
+    fn borrow_mut(&mut self) -> &mut Option<String> {
+        &mut self.0
+    }
+}
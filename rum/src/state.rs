@@ -3,7 +3,7 @@
 use crate::typemap::TypeMap;
 use std::borrow::{Borrow, BorrowMut};
 use std::ops::{Deref, DerefMut};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex as SyncMutex, PoisonError};
 use tokio::sync::Mutex;
 
 /// An extractor for a value of type `T` stored in the state management system.
@@ -105,3 +105,70 @@ impl LocalState {
         res
     }
 }
+
+/// The global state, as seen by the server's lifecycle hooks (see
+/// [`Server::on_startup`](crate::server::Server::on_startup) and
+/// [`Server::on_shutdown`](crate::server::Server::on_shutdown)). Values
+/// inserted by startup hooks become available to route handlers as
+/// [`State`]s.
+#[derive(Debug, Clone, Default)]
+pub struct ServerState(Arc<ServerStateInner>);
+
+/// The inner representation of the lifecycle hook state.
+#[derive(Debug, Default)]
+struct ServerStateInner {
+    /// The state shared with route handlers, once the server has started.
+    shared: Option<Arc<TypeMap>>,
+    /// Values owned by the hooks, taking precedence over the shared state.
+    local: SyncMutex<TypeMap>,
+}
+
+impl ServerState {
+    /// Creates the state seen by startup hooks, owning the global state.
+    pub(crate) fn startup(state: TypeMap) -> Self {
+        Self(Arc::new(ServerStateInner {
+            shared: None,
+            local: SyncMutex::new(state),
+        }))
+    }
+
+    /// Creates the state seen by shutdown hooks, referencing the global state
+    /// shared with route handlers.
+    pub(crate) fn shutdown(state: Arc<TypeMap>) -> Self {
+        Self(Arc::new(ServerStateInner {
+            shared: Some(state),
+            local: SyncMutex::default(),
+        }))
+    }
+
+    /// Moves the owned values out of this state.
+    pub(crate) fn take(&self) -> TypeMap {
+        std::mem::take(&mut *self.lock())
+    }
+
+    /// Locks the owned values.
+    fn lock(&self) -> std::sync::MutexGuard<'_, TypeMap> {
+        self.0.local.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Gets a clone of a value in the state.
+    pub fn get<T>(&self) -> Option<T>
+    where
+        T: Clone + 'static,
+    {
+        self.lock()
+            .get_cloned()
+            .or_else(|| self.0.shared.as_ref()?.get_cloned())
+    }
+
+    /// Inserts a value into the state. If a value of the same type is already
+    /// in the state, it will be replaced. Values inserted by startup hooks can
+    /// be extracted by route handlers as [`State`]s, and values inserted by
+    /// shutdown hooks are visible to subsequent shutdown hooks.
+    pub fn insert<T>(&self, value: T)
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.lock().insert(value);
+    }
+}
@@ -0,0 +1,508 @@
+//! TLS termination types.
+
+use crate::listener::{Addr, Listener, PeerInfo};
+use rustls_pemfile::{certs, private_key};
+use std::fmt::Debug;
+use std::io::{self, BufReader};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio_rustls::rustls::crypto::ring::default_provider;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::FromDer;
+
+/// The default amount of time a client is given to complete the TLS
+/// handshake.
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The default maximum number of TLS handshakes in progress at once.
+pub const DEFAULT_MAX_HANDSHAKES: usize = 1024;
+
+/// A source from which PEM data is loaded.
+#[derive(Clone)]
+enum PemSource {
+    /// A PEM file on disk. This is read again on every reload.
+    File(PathBuf),
+    /// PEM data held in memory.
+    Memory(Arc<[u8]>),
+}
+
+impl PemSource {
+    /// Reads the PEM data.
+    fn read(&self) -> io::Result<Vec<u8>> {
+        match self {
+            Self::File(path) => std::fs::read(path),
+            Self::Memory(data) => Ok(data.to_vec()),
+        }
+    }
+}
+
+impl Debug for PemSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File(path) => f.debug_tuple("File").field(path).finish(),
+            Self::Memory(_) => f.debug_tuple("Memory").finish_non_exhaustive(),
+        }
+    }
+}
+
+/// Whether clients must present a certificate during the TLS handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClientAuth {
+    /// Clients may present a certificate. If they do, it must be valid, but
+    /// connections without one are still accepted.
+    Optional,
+    /// Clients must present a valid certificate, otherwise the handshake
+    /// fails.
+    Required,
+}
+
+/// Marks a connection as secured by TLS.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TlsConnection;
+
+/// All sources used to build a `rustls` server configuration.
+#[derive(Debug, Clone)]
+struct TlsSources {
+    /// The PEM-encoded certificate chain.
+    cert: PemSource,
+    /// The PEM-encoded private key.
+    key: PemSource,
+    /// The PEM-encoded CA bundle used to verify client certificates, and
+    /// whether client certificates are required.
+    client_auth: Option<(PemSource, ClientAuth)>,
+}
+
+impl TlsSources {
+    /// Reads all sources and builds a `rustls` server configuration.
+    fn build(&self) -> io::Result<ServerConfig> {
+        let provider = Arc::new(default_provider());
+        let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+        let builder = match &self.client_auth {
+            Some((ca, client_auth)) => {
+                let mut roots = RootCertStore::empty();
+
+                for cert in parse_certs(&ca.read()?)? {
+                    roots
+                        .add(cert)
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                }
+
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+                let verifier = match client_auth {
+                    ClientAuth::Optional => verifier.allow_unauthenticated(),
+                    ClientAuth::Required => verifier,
+                }
+                .build()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let mut config = builder
+            .with_single_cert(
+                parse_certs(&self.cert.read()?)?,
+                parse_key(&self.key.read()?)?,
+            )
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        #[cfg(feature = "http2")]
+        config.alpn_protocols.push(b"h2".to_vec());
+        config.alpn_protocols.push(b"http/1.1".to_vec());
+
+        Ok(config)
+    }
+}
+
+/// Parses a PEM-encoded certificate chain.
+fn parse_certs(cert: &[u8]) -> io::Result<Vec<CertificateDer<'static>>> {
+    let certs = certs(&mut BufReader::new(cert)).collect::<io::Result<Vec<_>>>()?;
+
+    if certs.is_empty() {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "no certificates found in PEM data",
+        ))
+    } else {
+        Ok(certs)
+    }
+}
+
+/// Parses a PEM-encoded private key.
+fn parse_key(key: &[u8]) -> io::Result<PrivateKeyDer<'static>> {
+    private_key(&mut BufReader::new(key))?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "no private key found in PEM data",
+        )
+    })
+}
+
+/// A TLS server configuration, built from a PEM-encoded certificate chain and
+/// private key. Cloning this is cheap, and all clones share the same
+/// underlying configuration, so a reload through any clone applies to all of
+/// them. Reloading only affects new connections; established connections
+/// keep the certificate they were negotiated with.
+#[derive(Clone)]
+pub struct TlsConfig {
+    /// The sources of the certificates and keys.
+    sources: Arc<RwLock<TlsSources>>,
+    /// The current `rustls` server configuration.
+    config: Arc<RwLock<Arc<ServerConfig>>>,
+}
+
+impl TlsConfig {
+    /// Creates a TLS configuration from the given sources.
+    fn new(sources: TlsSources) -> io::Result<Self> {
+        let config = sources.build()?;
+
+        Ok(Self {
+            sources: Arc::new(RwLock::new(sources)),
+            config: Arc::new(RwLock::new(Arc::new(config))),
+        })
+    }
+
+    /// Creates a TLS configuration from PEM files containing the certificate
+    /// chain and the private key. The files are read again every time
+    /// [`reload`](Self::reload) is called.
+    pub fn from_pem_files<C, K>(cert_path: C, key_path: K) -> io::Result<Self>
+    where
+        C: AsRef<Path>,
+        K: AsRef<Path>,
+    {
+        Self::new(TlsSources {
+            cert: PemSource::File(cert_path.as_ref().to_owned()),
+            key: PemSource::File(key_path.as_ref().to_owned()),
+            client_auth: None,
+        })
+    }
+
+    /// Creates a TLS configuration from an in-memory PEM-encoded certificate
+    /// chain and private key.
+    pub fn from_pem(cert: &[u8], key: &[u8]) -> io::Result<Self> {
+        Self::new(TlsSources {
+            cert: PemSource::Memory(Arc::from(cert)),
+            key: PemSource::Memory(Arc::from(key)),
+            client_auth: None,
+        })
+    }
+
+    /// Enables client certificate verification against the CA bundle in the
+    /// given PEM file. The file is read again every time
+    /// [`reload`](Self::reload) is called. Verified client certificates can be
+    /// extracted from requests as a [`ClientCertificate`].
+    pub fn with_client_auth_pem_file<P>(
+        self,
+        ca_path: P,
+        client_auth: ClientAuth,
+    ) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        self.with_client_auth_source(PemSource::File(ca_path.as_ref().to_owned()), client_auth)
+    }
+
+    /// Enables client certificate verification against the given in-memory
+    /// PEM-encoded CA bundle. Verified client certificates can be extracted
+    /// from requests as a [`ClientCertificate`].
+    pub fn with_client_auth_pem(self, ca: &[u8], client_auth: ClientAuth) -> io::Result<Self> {
+        self.with_client_auth_source(PemSource::Memory(Arc::from(ca)), client_auth)
+    }
+
+    /// Enables client certificate verification using the given CA source.
+    fn with_client_auth_source(self, ca: PemSource, client_auth: ClientAuth) -> io::Result<Self> {
+        self.update(|sources| sources.client_auth = Some((ca, client_auth)))?;
+        Ok(self)
+    }
+
+    /// Reloads the certificate chain, private key and client CA bundle from
+    /// the configured sources. If anything cannot be loaded, the current
+    /// configuration is kept and the error is returned.
+    pub fn reload(&self) -> io::Result<()> {
+        self.update(|_| ())
+    }
+
+    /// Replaces the certificate chain and private key with new in-memory PEM
+    /// data. Subsequent calls to [`reload`](Self::reload) will use this data.
+    pub fn reload_from_pem(&self, cert: &[u8], key: &[u8]) -> io::Result<()> {
+        self.update(|sources| {
+            sources.cert = PemSource::Memory(Arc::from(cert));
+            sources.key = PemSource::Memory(Arc::from(key));
+        })
+    }
+
+    /// Applies a change to the configured sources and rebuilds the `rustls`
+    /// server configuration. The change is only kept if the rebuild succeeds.
+    fn update<F>(&self, f: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut TlsSources),
+    {
+        let mut sources = self.sources.write().unwrap();
+        let mut new_sources = sources.clone();
+        f(&mut new_sources);
+        let config = new_sources.build()?;
+        *sources = new_sources;
+        *self.config.write().unwrap() = Arc::new(config);
+
+        Ok(())
+    }
+
+    /// Creates a TLS acceptor using the current configuration.
+    pub(crate) fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(Arc::clone(&self.config.read().unwrap()))
+    }
+}
+
+impl Debug for TlsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("sources", &*self.sources.read().unwrap())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A listener that terminates TLS on every connection accepted by an inner
+/// listener. Handshakes run concurrently in the background, so a slow client
+/// does not hold up other connections. Connections whose handshake fails are
+/// dropped, as are connections that do not complete their handshake in time.
+/// Once the maximum number of handshakes is in progress, no more connections
+/// are accepted from the inner listener until one of them finishes. Reloading
+/// the [`TlsConfig`] while the listener is in use applies to all connections
+/// accepted afterwards.
+pub struct TlsListener<L>
+where
+    L: Listener,
+{
+    /// The inner listener.
+    inner: L,
+    /// The TLS configuration.
+    config: TlsConfig,
+    /// The amount of time a client is given to complete the handshake.
+    handshake_timeout: Duration,
+    /// The maximum number of handshakes in progress at once.
+    max_handshakes: usize,
+    /// The handshakes currently in progress.
+    handshakes: JoinSet<io::Result<(TlsStream<L::Io>, PeerInfo)>>,
+}
+
+impl<L> TlsListener<L>
+where
+    L: Listener,
+{
+    /// Wraps a listener to terminate TLS on its connections.
+    pub fn new(inner: L, config: TlsConfig) -> Self {
+        Self {
+            inner,
+            config,
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+            max_handshakes: DEFAULT_MAX_HANDSHAKES,
+            handshakes: JoinSet::new(),
+        }
+    }
+
+    /// Sets the amount of time a client is given to complete the handshake.
+    /// The default is [`DEFAULT_HANDSHAKE_TIMEOUT`].
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum number of handshakes in progress at once. A limit of
+    /// zero is treated as one. The default is [`DEFAULT_MAX_HANDSHAKES`].
+    pub fn with_max_handshakes(mut self, max: usize) -> Self {
+        self.max_handshakes = max.max(1);
+        self
+    }
+
+    /// Performs the TLS handshake on a connection, recording the client's
+    /// certificate, if it presented one.
+    async fn handshake(
+        acceptor: TlsAcceptor,
+        conn: L::Io,
+        mut peer: PeerInfo,
+    ) -> io::Result<(TlsStream<L::Io>, PeerInfo)> {
+        let conn = acceptor.accept(conn).await?;
+        peer.insert(TlsConnection);
+
+        if let Some(client_certificate) = conn
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(ClientCertificate::from_chain)
+        {
+            peer.insert(client_certificate);
+        }
+
+        Ok((conn, peer))
+    }
+}
+
+impl<L> Listener for TlsListener<L>
+where
+    L: Listener,
+{
+    type Io = TlsStream<L::Io>;
+
+    async fn accept(&mut self) -> io::Result<(Self::Io, PeerInfo)> {
+        loop {
+            tokio::select! {
+                conn = self.inner.accept(), if self.handshakes.len() < self.max_handshakes => {
+                    let (conn, peer) = conn?;
+                    let handshake = Self::handshake(self.config.acceptor(), conn, peer);
+                    let timeout = self.handshake_timeout;
+
+                    self.handshakes.spawn(async move {
+                        tokio::time::timeout(timeout, handshake)
+                            .await
+                            .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))?
+                    });
+                }
+                Some(handshake) = self.handshakes.join_next(), if !self.handshakes.is_empty() => {
+                    if let Ok(Ok(conn)) = handshake {
+                        return Ok(conn);
+                    }
+                }
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Addr> {
+        self.inner.local_addr()
+    }
+}
+
+impl<L> Debug for TlsListener<L>
+where
+    L: Listener + Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsListener")
+            .field("inner", &self.inner)
+            .field("config", &self.config)
+            .field("handshake_timeout", &self.handshake_timeout)
+            .field("max_handshakes", &self.max_handshakes)
+            .field("handshakes", &self.handshakes.len())
+            .finish()
+    }
+}
+
+/// A subject alternative name from a client certificate.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SubjectAltName {
+    /// A DNS name.
+    Dns(String),
+    /// An IP address.
+    Ip(IpAddr),
+    /// An email address.
+    Email(String),
+    /// A URI.
+    Uri(String),
+}
+
+/// An extractor for the verified certificate chain presented by the client
+/// during the TLS handshake. This is only available on connections served
+/// with client authentication enabled (see
+/// [`TlsConfig::with_client_auth_pem`]) where the client presented a
+/// certificate. Extraction fails with a 401 response otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    /// The DER-encoded certificate chain, starting with the client's own
+    /// certificate.
+    chain: Arc<[Vec<u8>]>,
+    /// The subject distinguished name of the client's certificate.
+    subject: String,
+    /// The subject common name of the client's certificate, if it has one.
+    common_name: Option<String>,
+    /// The subject alternative names of the client's certificate.
+    subject_alt_names: Arc<[SubjectAltName]>,
+}
+
+impl ClientCertificate {
+    /// Parses the peer certificate chain of a TLS connection. Returns `None`
+    /// if the chain is empty or the client's certificate cannot be parsed.
+    pub(crate) fn from_chain(chain: &[CertificateDer<'_>]) -> Option<Self> {
+        let (_, leaf) = X509Certificate::from_der(chain.first()?).ok()?;
+
+        let subject_alt_names: Arc<[SubjectAltName]> = match leaf.subject_alternative_name() {
+            Ok(Some(san)) => san
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(name) => Some(SubjectAltName::Dns((*name).to_owned())),
+                    GeneralName::RFC822Name(email) => {
+                        Some(SubjectAltName::Email((*email).to_owned()))
+                    }
+                    GeneralName::URI(uri) => Some(SubjectAltName::Uri((*uri).to_owned())),
+                    GeneralName::IPAddress(bytes) => match bytes.len() {
+                        4 => Some(SubjectAltName::Ip(IpAddr::from(
+                            <[u8; 4]>::try_from(*bytes).ok()?,
+                        ))),
+                        16 => Some(SubjectAltName::Ip(IpAddr::from(
+                            <[u8; 16]>::try_from(*bytes).ok()?,
+                        ))),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect(),
+            _ => Arc::new([]),
+        };
+
+        let common_name = leaf
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(ToOwned::to_owned);
+
+        Some(Self {
+            chain: chain.iter().map(|cert| cert.to_vec()).collect(),
+            subject: leaf.subject().to_string(),
+            common_name,
+            subject_alt_names,
+        })
+    }
+
+    /// Gets the DER-encoded certificate chain presented by the client,
+    /// starting with the client's own certificate.
+    pub fn chain(&self) -> &[Vec<u8>] {
+        &self.chain
+    }
+
+    /// Gets the DER-encoded certificate of the client.
+    pub fn leaf(&self) -> &[u8] {
+        &self.chain[0]
+    }
+
+    /// Gets the subject distinguished name of the client's certificate, e.g.
+    /// `"CN=client, O=Example"`.
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    /// Gets the subject common name of the client's certificate, if it has
+    /// one.
+    pub fn common_name(&self) -> Option<&str> {
+        self.common_name.as_deref()
+    }
+
+    /// Gets the subject alternative names of the client's certificate.
+    pub fn subject_alt_names(&self) -> &[SubjectAltName] {
+        &self.subject_alt_names
+    }
+}
@@ -0,0 +1,78 @@
+//! Connection upgrades and tunnels.
+
+use crate::error::Result;
+use hyper_util::rt::TokioIo;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// An extractor for the pending upgrade of the connection a request arrived
+/// on. This resolves to the raw connection once the handler has responded
+/// with `101 Switching Protocols`, or with any successful status code to a
+/// `CONNECT` request. If the handler responds in any other way, this resolves
+/// to an error. `CONNECT` requests are routed as requests for `/`, and the
+/// address the client wants to tunnel to is available through
+/// [`RequestInner::authority`](crate::request::RequestInner::authority).
+///
+/// Extraction fails if the connection cannot be upgraded, e.g. because the
+/// request is not a `CONNECT` request and was made using HTTP/2, or if the
+/// upgrade has already been extracted.
+#[derive(Debug)]
+pub struct OnUpgrade(pub(crate) hyper::upgrade::OnUpgrade);
+
+impl Future for OnUpgrade {
+    type Output = Result<Upgraded>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0)
+            .poll(cx)
+            .map(|res| Ok(Upgraded(TokioIo::new(res?))))
+    }
+}
+
+/// An upgraded connection, or the client end of a `CONNECT` tunnel. Any bytes
+/// the client sent after the request are read first.
+#[derive(Debug)]
+pub struct Upgraded(TokioIo<hyper::upgrade::Upgraded>);
+
+impl AsyncRead for Upgraded {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Upgraded {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.0.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
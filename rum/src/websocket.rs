@@ -0,0 +1,377 @@
+//! WebSocket connections.
+
+use crate::error::{Error, Result};
+use crate::http::{Method, StatusCode, Version};
+use crate::request::Request;
+use crate::response::Response;
+use crate::upgrade::{OnUpgrade, Upgraded};
+use futures_core::Stream;
+use futures_util::{Sink, SinkExt, StreamExt};
+use std::borrow::Cow;
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::{self, frame, Role, WebSocketConfig};
+use tokio_tungstenite::WebSocketStream;
+
+/// The only WebSocket protocol version supported.
+const WEBSOCKET_VERSION: &str = "13";
+
+/// The length of a valid `Sec-WebSocket-Key` header, which is a base64
+/// encoded 16 byte value.
+const WEBSOCKET_KEY_LEN: usize = 24;
+
+/// Checks whether a comma-separated header contains the given token, ignoring
+/// case.
+fn header_contains_token(values: Option<&[String]>, token: &str) -> bool {
+    values.unwrap_or_default().iter().any(|value| {
+        value
+            .split(',')
+            .any(|item| item.trim().eq_ignore_ascii_case(token))
+    })
+}
+
+/// A request to upgrade the connection to a WebSocket. Extracting this
+/// validates the handshake headers, and responding with
+/// [`on_upgrade`](Self::on_upgrade) completes the handshake. Since this is an
+/// ordinary extractor, routing and middleware are applied to the request
+/// before the upgrade, so middleware can e.g. reject unauthenticated clients.
+///
+/// Only HTTP/1.1 connections can be upgraded.
+pub struct WebSocketUpgrade {
+    /// The value of the `Sec-WebSocket-Key` header.
+    key: String,
+    /// The subprotocols requested by the client, in order of preference.
+    requested_protocols: Vec<String>,
+    /// The negotiated subprotocol.
+    protocol: Option<String>,
+    /// The WebSocket protocol configuration.
+    config: WebSocketConfig,
+    /// The pending connection upgrade.
+    on_upgrade: OnUpgrade,
+}
+
+impl WebSocketUpgrade {
+    /// Validates the WebSocket handshake headers of a request and takes the
+    /// pending connection upgrade.
+    pub(crate) fn new(req: &Request) -> Result<Self> {
+        if *req.method() != Method::GET {
+            return Err(Error::InvalidWebSocketHandshake(
+                "the request method must be GET",
+            ));
+        }
+
+        if req.version() != Version::HTTP_11 {
+            return Err(Error::InvalidWebSocketHandshake(
+                "the request must be made using HTTP/1.1",
+            ));
+        }
+
+        if !header_contains_token(req.header_optional("Connection"), "upgrade") {
+            return Err(Error::InvalidWebSocketHandshake(
+                "the Connection header must contain 'upgrade'",
+            ));
+        }
+
+        if !header_contains_token(req.header_optional("Upgrade"), "websocket") {
+            return Err(Error::InvalidWebSocketHandshake(
+                "the Upgrade header must contain 'websocket'",
+            ));
+        }
+
+        match req.header_optional("Sec-WebSocket-Version") {
+            Some([version]) if version.trim() == WEBSOCKET_VERSION => {}
+            Some(_) => return Err(Error::UnsupportedWebSocketVersion),
+            None => {
+                return Err(Error::InvalidWebSocketHandshake(
+                    "missing Sec-WebSocket-Version header",
+                ))
+            }
+        }
+
+        let key = match req.header_optional("Sec-WebSocket-Key") {
+            Some([key]) if key.trim().len() == WEBSOCKET_KEY_LEN => key.trim().to_owned(),
+            _ => {
+                return Err(Error::InvalidWebSocketHandshake(
+                    "missing or invalid Sec-WebSocket-Key header",
+                ))
+            }
+        };
+
+        let requested_protocols = req
+            .header_optional("Sec-WebSocket-Protocol")
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|protocol| !protocol.is_empty())
+            .map(ToOwned::to_owned)
+            .collect();
+
+        let on_upgrade = req.take_upgrade().ok_or(Error::UpgradeUnavailable)?;
+
+        Ok(Self {
+            key,
+            requested_protocols,
+            protocol: None,
+            config: WebSocketConfig::default(),
+            on_upgrade,
+        })
+    }
+
+    /// Gets the subprotocols requested by the client, in order of preference.
+    pub fn requested_protocols(&self) -> &[String] {
+        &self.requested_protocols
+    }
+
+    /// Gets the negotiated subprotocol, if any.
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// Negotiates a subprotocol. The first of the given protocols, in order of
+    /// the server's preference, that was requested by the client is selected.
+    /// If none of them were requested, no subprotocol is selected.
+    pub fn with_protocols<I, S>(mut self, protocols: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.protocol = protocols.into_iter().find_map(|protocol| {
+            self.requested_protocols
+                .iter()
+                .find(|requested| *requested == protocol.as_ref())
+                .cloned()
+        });
+        self
+    }
+
+    /// Sets the maximum size of an incoming message. Receiving a larger
+    /// message fails with an error. The default is 64 MiB.
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.config.max_message_size = Some(max_message_size);
+        self
+    }
+
+    /// Sets the maximum size of a single incoming frame. The default is
+    /// 16 MiB.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.config.max_frame_size = Some(max_frame_size);
+        self
+    }
+
+    /// Completes the handshake, returning the response that switches the
+    /// connection to the WebSocket protocol. Once the response has been sent,
+    /// the callback is run in a new task with the resulting socket. If the
+    /// response is replaced, e.g. by middleware, the callback is never run.
+    ///
+    /// The socket is not tracked by the server, so it is not closed when the
+    /// server shuts down.
+    pub fn on_upgrade<F, Fut>(self, callback: F) -> Response
+    where
+        F: FnOnce(WebSocket) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let res = Response::new()
+            .status_code(StatusCode::SWITCHING_PROTOCOLS)
+            .header("Connection", "upgrade")
+            .header("Upgrade", "websocket")
+            .header(
+                "Sec-WebSocket-Accept",
+                &derive_accept_key(self.key.as_bytes()),
+            );
+        let res = match &self.protocol {
+            Some(protocol) => res.header("Sec-WebSocket-Protocol", protocol),
+            None => res,
+        };
+
+        tokio::spawn(async move {
+            if let Ok(upgraded) = self.on_upgrade.await {
+                let inner =
+                    WebSocketStream::from_raw_socket(upgraded, Role::Server, Some(self.config))
+                        .await;
+
+                callback(WebSocket {
+                    inner,
+                    protocol: self.protocol,
+                })
+                .await;
+            }
+        });
+
+        res
+    }
+}
+
+impl Debug for WebSocketUpgrade {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebSocketUpgrade")
+            .field("requested_protocols", &self.requested_protocols)
+            .field("protocol", &self.protocol)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The payload of a WebSocket close message.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CloseFrame {
+    /// The close status code.
+    pub code: u16,
+    /// The reason the connection was closed.
+    pub reason: String,
+}
+
+impl From<frame::CloseFrame<'_>> for CloseFrame {
+    fn from(value: frame::CloseFrame<'_>) -> Self {
+        Self {
+            code: value.code.into(),
+            reason: value.reason.into_owned(),
+        }
+    }
+}
+
+impl From<CloseFrame> for frame::CloseFrame<'static> {
+    fn from(value: CloseFrame) -> Self {
+        Self {
+            code: value.code.into(),
+            reason: Cow::Owned(value.reason),
+        }
+    }
+}
+
+/// A WebSocket message.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Message {
+    /// A UTF-8 text message.
+    Text(String),
+    /// A binary message.
+    Binary(Vec<u8>),
+    /// A ping message. Pings are answered automatically, but are still
+    /// received so that they can be observed.
+    Ping(Vec<u8>),
+    /// A pong message.
+    Pong(Vec<u8>),
+    /// A close message. Once this has been received, no further messages
+    /// will be.
+    Close(Option<CloseFrame>),
+}
+
+impl From<String> for Message {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl From<&str> for Message {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_owned())
+    }
+}
+
+impl From<Vec<u8>> for Message {
+    fn from(value: Vec<u8>) -> Self {
+        Self::Binary(value)
+    }
+}
+
+impl From<protocol::Message> for Message {
+    fn from(value: protocol::Message) -> Self {
+        match value {
+            protocol::Message::Text(text) => Self::Text(text),
+            protocol::Message::Binary(data) => Self::Binary(data),
+            protocol::Message::Ping(data) => Self::Ping(data),
+            protocol::Message::Pong(data) => Self::Pong(data),
+            protocol::Message::Close(frame) => Self::Close(frame.map(Into::into)),
+            // Raw frames are never produced when reading.
+            protocol::Message::Frame(frame) => Self::Binary(frame.into_data()),
+        }
+    }
+}
+
+impl From<Message> for protocol::Message {
+    fn from(value: Message) -> Self {
+        match value {
+            Message::Text(text) => Self::Text(text),
+            Message::Binary(data) => Self::Binary(data),
+            Message::Ping(data) => Self::Ping(data),
+            Message::Pong(data) => Self::Pong(data),
+            Message::Close(frame) => Self::Close(frame.map(Into::into)),
+        }
+    }
+}
+
+/// An upgraded WebSocket connection. Messages can be received through
+/// [`recv`](Self::recv) or the [`Stream`] implementation, and sent through
+/// [`send`](Self::send) or the [`Sink`] implementation.
+pub struct WebSocket {
+    /// The underlying WebSocket stream.
+    inner: WebSocketStream<Upgraded>,
+    /// The negotiated subprotocol.
+    protocol: Option<String>,
+}
+
+impl WebSocket {
+    /// Gets the negotiated subprotocol, if any.
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// Receives the next message. This returns `None` once the connection
+    /// has been closed.
+    pub async fn recv(&mut self) -> Option<Result<Message>> {
+        self.next().await
+    }
+
+    /// Sends a message.
+    pub async fn send<M>(&mut self, message: M) -> Result<()>
+    where
+        M: Into<Message>,
+    {
+        SinkExt::send(self, message.into()).await
+    }
+
+    /// Closes the connection, optionally telling the client why.
+    pub async fn close(mut self, frame: Option<CloseFrame>) -> Result<()> {
+        Ok(self.inner.close(frame.map(Into::into)).await?)
+    }
+}
+
+impl Debug for WebSocket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebSocket")
+            .field("protocol", &self.protocol)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Stream for WebSocket {
+    type Item = Result<Message>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner
+            .poll_next_unpin(cx)
+            .map(|item| item.map(|res| res.map(Into::into).map_err(Into::into)))
+    }
+}
+
+impl Sink<Message> for WebSocket {
+    type Error = Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.inner.poll_ready_unpin(cx).map_err(Into::into)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Message) -> Result<()> {
+        self.inner.start_send_unpin(item.into()).map_err(Into::into)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.inner.poll_flush_unpin(cx).map_err(Into::into)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.inner.poll_close_unpin(cx).map_err(Into::into)
+    }
+}
@@ -7,6 +7,7 @@ use rum::response::ResponseInner;
 use rum::routing::{
     CompleteRouteHandler, RouteHandler, RouteLevel, RoutePathMatchedSegment, RoutePathSegment,
 };
+use rum::tls::SubjectAltName;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::borrow::{Borrow, BorrowMut};
@@ -20,26 +21,20 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::net::TcpListener;
 use tokio::spawn;
-use tokio::task::JoinHandle;
 
 struct TestServer {
     server: Server,
-    shutdown_sender: ShutdownSender,
     error_receiver: ErrorReceiver,
 }
 
 impl TestServer {
     pub fn new() -> Self {
-        let (shutdown_sender, shutdown_receiver) = shutdown_signal();
         let (error_sender, error_receiver) = error_report_stream();
 
-        let server = Server::new()
-            .with_graceful_shutdown(shutdown_receiver)
-            .with_error_reporting(error_sender);
+        let server = Server::new().with_error_reporting(error_sender);
 
         Self {
             server,
-            shutdown_sender,
             error_receiver,
         }
     }
@@ -53,46 +48,82 @@ impl TestServer {
     }
 
     pub async fn start(self) -> io::Result<TestServerHandle> {
-        let server = self.server;
+        let server = self.server.bind("127.0.0.1:0").await?;
+
+        Ok(TestServerHandle::new(
+            server,
+            "http",
+            self.error_receiver,
+            reqwest::Client::new(),
+        ))
+    }
+
+    pub async fn start_tls(
+        self,
+        tls_config: TlsConfig,
+        root_cert: &str,
+    ) -> io::Result<TestServerHandle> {
         let listener = TcpListener::bind("127.0.0.1:0").await?;
-        let port = listener.local_addr()?.port();
+        self.start_tls_listener(TlsListener::new(listener, tls_config), root_cert)
+            .await
+    }
 
-        let serve_task = spawn(async move { server.serve_with(listener).await });
+    pub async fn start_tls_listener(
+        self,
+        listener: TlsListener<TcpListener>,
+        root_cert: &str,
+    ) -> io::Result<TestServerHandle> {
+        let server = self.server.bind_with(listener).await?;
 
-        Ok(TestServerHandle {
-            port,
-            serve_task,
-            shutdown_sender: self.shutdown_sender,
-            error_receiver: self.error_receiver,
-            client: reqwest::Client::new(),
-        })
+        Ok(TestServerHandle::new(
+            server,
+            "https",
+            self.error_receiver,
+            tls_client(root_cert),
+        ))
     }
 
     pub async fn start_on(self, port: u16) -> io::Result<TestServerHandle> {
-        let server = self.server;
+        let server = self.server.bind(("127.0.0.1", port)).await?;
 
-        let serve_task = spawn(async move { server.serve(("127.0.0.1", port)).await.unwrap() });
-
-        Ok(TestServerHandle {
-            port,
-            serve_task,
-            shutdown_sender: self.shutdown_sender,
-            error_receiver: self.error_receiver,
-            client: reqwest::Client::new(),
-        })
+        Ok(TestServerHandle::new(
+            server,
+            "http",
+            self.error_receiver,
+            reqwest::Client::new(),
+        ))
     }
 }
 
 struct TestServerHandle {
     port: u16,
-    serve_task: JoinHandle<()>,
-    shutdown_sender: ShutdownSender,
+    scheme: &'static str,
+    server: RunningServer,
     error_receiver: ErrorReceiver,
     client: reqwest::Client,
 }
 
 #[allow(dead_code)]
 impl TestServerHandle {
+    fn new(
+        server: RunningServer,
+        scheme: &'static str,
+        error_receiver: ErrorReceiver,
+        client: reqwest::Client,
+    ) -> Self {
+        let Addr::Tcp(addr) = server.local_addr() else {
+            panic!("expected a TCP address");
+        };
+
+        Self {
+            port: addr.port(),
+            scheme,
+            server,
+            error_receiver,
+            client,
+        }
+    }
+
     pub async fn query<F>(
         &self,
         method: Method,
@@ -105,7 +136,8 @@ impl TestServerHandle {
         let req = self.client.request(
             method,
             format!(
-                "http://localhost:{}/{}",
+                "{}://localhost:{}/{}",
+                self.scheme,
                 self.port,
                 path.strip_prefix('/').unwrap_or_default()
             ),
@@ -265,8 +297,7 @@ impl TestServerHandle {
     }
 
     pub async fn stop(mut self) -> Vec<Arc<Error>> {
-        self.shutdown_sender.shutdown().await;
-        self.serve_task.await.unwrap();
+        self.server.shutdown().await;
 
         let mut errors = Vec::new();
 
@@ -278,6 +309,119 @@ impl TestServerHandle {
     }
 }
 
+fn self_signed_cert() -> (String, String) {
+    let rcgen::CertifiedKey { cert, key_pair } =
+        rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+
+    (cert.pem(), key_pair.serialize_pem())
+}
+
+fn test_ca() -> (rcgen::Certificate, rcgen::KeyPair) {
+    let mut params = rcgen::CertificateParams::new(Vec::<String>::new()).unwrap();
+    params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, "Rum Test CA");
+    let key = rcgen::KeyPair::generate().unwrap();
+    let cert = params.self_signed(&key).unwrap();
+
+    (cert, key)
+}
+
+fn client_identity(
+    ca: &(rcgen::Certificate, rcgen::KeyPair),
+    common_name: &str,
+    subject_alt_names: &[&str],
+) -> String {
+    let mut params = rcgen::CertificateParams::new(
+        subject_alt_names
+            .iter()
+            .map(|name| (*name).to_owned())
+            .collect::<Vec<_>>(),
+    )
+    .unwrap();
+    params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, common_name);
+    params.extended_key_usages = vec![rcgen::ExtendedKeyUsagePurpose::ClientAuth];
+    let key = rcgen::KeyPair::generate().unwrap();
+    let cert = params.signed_by(&key, &ca.0, &ca.1).unwrap();
+
+    format!("{}{}", cert.pem(), key.serialize_pem())
+}
+
+fn tls_client_with_identity(root_cert: &str, identity: &str) -> reqwest::Client {
+    reqwest::Client::builder()
+        .use_rustls_tls()
+        .tls_built_in_root_certs(false)
+        .add_root_certificate(reqwest::Certificate::from_pem(root_cert.as_bytes()).unwrap())
+        .identity(reqwest::Identity::from_pem(identity.as_bytes()).unwrap())
+        .build()
+        .unwrap()
+}
+
+fn tls_client(root_cert: &str) -> reqwest::Client {
+    reqwest::Client::builder()
+        .use_rustls_tls()
+        .tls_built_in_root_certs(false)
+        .add_root_certificate(reqwest::Certificate::from_pem(root_cert.as_bytes()).unwrap())
+        .build()
+        .unwrap()
+}
+
+#[cfg(unix)]
+fn temp_socket_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("rum-test-{}-{}.sock", name, std::process::id()))
+}
+
+async fn http1_get<S>(stream: S, uri: &str) -> (StatusCode, String)
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    use http_body_util::BodyExt;
+
+    let (mut sender, conn) =
+        hyper::client::conn::http1::handshake(hyper_util::rt::TokioIo::new(stream))
+            .await
+            .unwrap();
+    spawn(conn);
+
+    let req = http::Request::builder()
+        .uri(uri)
+        .header("Host", "localhost")
+        .body(http_body_util::Empty::<hyper::body::Bytes>::new())
+        .unwrap();
+    let res = sender.send_request(req).await.unwrap();
+    let status = res.status();
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[cfg(unix)]
+async fn unix_get(path: &std::path::Path, uri: &str) -> (StatusCode, String) {
+    let stream = tokio::net::UnixStream::connect(path).await.unwrap();
+    http1_get(stream, uri).await
+}
+
+async fn read_response_head(stream: &mut tokio::net::TcpStream) -> String {
+    use tokio::io::AsyncReadExt;
+
+    let mut head = Vec::new();
+
+    while !head.ends_with(b"\r\n\r\n") {
+        let mut byte = [0];
+
+        if stream.read(&mut byte).await.unwrap() == 0 {
+            break;
+        }
+
+        head.push(byte[0]);
+    }
+
+    String::from_utf8(head).unwrap()
+}
+
 fn assert_inner<T, U>(outer: &T, inner: &U)
 where
     T: Deref<Target = U> + Borrow<U> + ?Sized,
@@ -1222,6 +1366,29 @@ async fn test_extract_method() {
     assert_no_server_errors!(errors);
 }
 
+#[tokio::test]
+async fn test_extract_version() {
+    async fn extract_version(req: Request) -> Response {
+        let version = Version::from_request(&req).unwrap();
+        assert_eq!(version, Version::HTTP_11);
+        assert_eq!(req.version(), Version::HTTP_11);
+
+        Response::new()
+    }
+
+    let server = TestServer::new()
+        .config(|server| server.get("/test", extract_version))
+        .start()
+        .await
+        .unwrap();
+
+    let res = server.get("/test", |req| req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
 #[tokio::test]
 async fn test_extract_route_path_string() {
     async fn extract_route_path(req: Request) -> Response {
@@ -5212,3 +5379,2690 @@ async fn test_serve_with_address() {
     let errors = server.stop().await;
     assert_no_server_errors!(errors);
 }
+
+#[tokio::test]
+async fn test_serve_tls() {
+    #[handler]
+    async fn final_handler() -> &'static str {
+        "Success"
+    }
+
+    let (cert, key) = self_signed_cert();
+    let tls_config = TlsConfig::from_pem(cert.as_bytes(), key.as_bytes()).unwrap();
+
+    let server = TestServer::new()
+        .config(|server| server.get("/test", final_handler))
+        .start_tls(tls_config, &cert)
+        .await
+        .unwrap();
+
+    let res = server.get("/test", |req| req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "Success");
+
+    let plain_res = reqwest::get(format!("http://localhost:{}/test", server.port)).await;
+    assert!(plain_res.is_err());
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_tls_handshake_timeout() {
+    use tokio::io::AsyncReadExt;
+
+    #[handler]
+    async fn final_handler() -> &'static str {
+        "Success"
+    }
+
+    let (cert, key) = self_signed_cert();
+    let tls_config = TlsConfig::from_pem(cert.as_bytes(), key.as_bytes()).unwrap();
+    let listener = TlsListener::new(TcpListener::bind("127.0.0.1:0").await.unwrap(), tls_config)
+        .with_handshake_timeout(Duration::from_millis(300))
+        .with_max_handshakes(1);
+
+    let server = TestServer::new()
+        .config(|server| server.get("/test", final_handler))
+        .start_tls_listener(listener, &cert)
+        .await
+        .unwrap();
+
+    // A client that never sends a ClientHello holds the only handshake slot
+    // until it times out.
+    let start = Instant::now();
+    let mut stalled = tokio::net::TcpStream::connect(("127.0.0.1", server.port))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let res = server.get("/test", |req| req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "Success");
+    assert!(start.elapsed() >= Duration::from_millis(250));
+
+    let closed = tokio::time::timeout(Duration::from_secs(5), stalled.read(&mut [0]))
+        .await
+        .unwrap();
+    assert!(matches!(closed, Ok(0) | Err(_)));
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_tls_config_reload() {
+    #[handler]
+    async fn final_handler() -> &'static str {
+        "Success"
+    }
+
+    let (cert1, key1) = self_signed_cert();
+    let (cert2, key2) = self_signed_cert();
+
+    let dir = std::env::temp_dir().join(format!("rum-test-tls-reload-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+    std::fs::write(&cert_path, &cert1).unwrap();
+    std::fs::write(&key_path, &key1).unwrap();
+
+    let tls_config = TlsConfig::from_pem_files(&cert_path, &key_path).unwrap();
+
+    let server = TestServer::new()
+        .config(|server| server.get("/test", final_handler))
+        .start_tls(tls_config.clone(), &cert1)
+        .await
+        .unwrap();
+    let url = format!("https://localhost:{}/test", server.port);
+
+    let res = tls_client(&cert1).get(&url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(tls_client(&cert2).get(&url).send().await.is_err());
+
+    std::fs::write(&cert_path, &cert2).unwrap();
+    std::fs::write(&key_path, &key2).unwrap();
+    tls_config.reload().unwrap();
+
+    let res = tls_client(&cert2).get(&url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(tls_client(&cert1).get(&url).send().await.is_err());
+
+    std::fs::write(&key_path, "not a key").unwrap();
+    assert!(tls_config.reload().is_err());
+
+    let res = tls_client(&cert2).get(&url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    tls_config
+        .reload_from_pem(cert1.as_bytes(), key1.as_bytes())
+        .unwrap();
+
+    let res = tls_client(&cert1).get(&url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[test]
+fn test_tls_config_invalid_pem() {
+    let (cert, key) = self_signed_cert();
+
+    assert!(TlsConfig::from_pem(b"", key.as_bytes()).is_err());
+    assert!(TlsConfig::from_pem(cert.as_bytes(), b"").is_err());
+    assert!(TlsConfig::from_pem(key.as_bytes(), cert.as_bytes()).is_err());
+    assert!(TlsConfig::from_pem_files("/nonexistent/cert.pem", "/nonexistent/key.pem").is_err());
+}
+
+#[tokio::test]
+async fn test_mutual_tls_required() {
+    #[handler]
+    async fn final_handler(client_certificate: ClientCertificate) -> String {
+        assert_eq!(client_certificate.chain().len(), 1);
+        assert_eq!(
+            client_certificate.leaf(),
+            &client_certificate.chain()[0][..]
+        );
+        assert_eq!(
+            client_certificate.subject_alt_names(),
+            &[
+                SubjectAltName::Dns("client.local".to_owned()),
+                SubjectAltName::Ip("10.0.0.1".parse().unwrap()),
+            ]
+        );
+        assert_eq!(client_certificate.subject(), "CN=internal-service");
+        client_certificate.common_name().unwrap().to_owned()
+    }
+
+    let (cert, key) = self_signed_cert();
+    let ca = test_ca();
+    let tls_config = TlsConfig::from_pem(cert.as_bytes(), key.as_bytes())
+        .unwrap()
+        .with_client_auth_pem(ca.0.pem().as_bytes(), ClientAuth::Required)
+        .unwrap();
+
+    let server = TestServer::new()
+        .config(|server| server.get("/test", final_handler))
+        .start_tls(tls_config, &cert)
+        .await
+        .unwrap();
+    let url = format!("https://localhost:{}/test", server.port);
+
+    assert!(server.get("/test", |req| req).await.is_err());
+
+    let identity = client_identity(&ca, "internal-service", &["client.local", "10.0.0.1"]);
+    let res = tls_client_with_identity(&cert, &identity)
+        .get(&url)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "internal-service");
+
+    let untrusted_identity = client_identity(&test_ca(), "intruder", &["client.local"]);
+    assert!(tls_client_with_identity(&cert, &untrusted_identity)
+        .get(&url)
+        .send()
+        .await
+        .is_err());
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_mutual_tls_optional() {
+    #[handler]
+    async fn required_handler(client_certificate: ClientCertificate) -> String {
+        client_certificate.common_name().unwrap().to_owned()
+    }
+
+    #[handler]
+    async fn optional_handler(req: Request) -> String {
+        req.client_certificate()
+            .and_then(|cert| cert.common_name().map(ToOwned::to_owned))
+            .unwrap_or_else(|| "anonymous".to_owned())
+    }
+
+    let (cert, key) = self_signed_cert();
+    let ca = test_ca();
+    let tls_config = TlsConfig::from_pem(cert.as_bytes(), key.as_bytes())
+        .unwrap()
+        .with_client_auth_pem(ca.0.pem().as_bytes(), ClientAuth::Optional)
+        .unwrap();
+
+    let server = TestServer::new()
+        .config(|server| {
+            server
+                .get("/required", required_handler)
+                .get("/optional", optional_handler)
+        })
+        .start_tls(tls_config, &cert)
+        .await
+        .unwrap();
+
+    let res = server.get("/required", |req| req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = server.get("/optional", |req| req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "anonymous");
+
+    let identity = client_identity(&ca, "internal-service", &[]);
+    let client = tls_client_with_identity(&cert, &identity);
+
+    for path in ["required", "optional"] {
+        let res = client
+            .get(format!("https://localhost:{}/{}", server.port, path))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.text().await.unwrap(), "internal-service");
+    }
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_missing_client_certificate_without_tls() {
+    #[handler]
+    async fn final_handler(_: ClientCertificate) {}
+
+    let server = TestServer::new()
+        .config(|server| server.get("/test", final_handler))
+        .start()
+        .await
+        .unwrap();
+
+    let res = server.get("/test", |req| req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_http2_prior_knowledge() {
+    #[handler]
+    async fn final_handler(version: Version) -> String {
+        format!("{:?}", version)
+    }
+
+    let server = TestServer::new()
+        .config(|server| {
+            server.get("/test", final_handler).with_http2_config(
+                Http2Config::new()
+                    .max_concurrent_streams(16)
+                    .initial_stream_window_size(1 << 20)
+                    .initial_connection_window_size(1 << 21)
+                    .max_frame_size(1 << 15)
+                    .max_header_list_size(1 << 14)
+                    .keep_alive_interval(Duration::from_secs(10))
+                    .keep_alive_timeout(Duration::from_secs(5)),
+            )
+        })
+        .start()
+        .await
+        .unwrap();
+
+    let res = server.get("/test", |req| req).await.unwrap();
+    assert_eq!(res.version(), reqwest::Version::HTTP_11);
+    assert_eq!(res.text().await.unwrap(), "HTTP/1.1");
+
+    let client = reqwest::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    let requests = (0..32).map(|_| {
+        client
+            .get(format!("http://localhost:{}/test", server.port))
+            .send()
+    });
+
+    for res in futures_util::future::join_all(requests).await {
+        let res = res.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.version(), reqwest::Version::HTTP_2);
+        assert_eq!(res.text().await.unwrap(), "HTTP/2.0");
+    }
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_http2_tls_alpn() {
+    #[handler]
+    async fn final_handler(version: Version) -> String {
+        format!("{:?}", version)
+    }
+
+    let (cert, key) = self_signed_cert();
+    let tls_config = TlsConfig::from_pem(cert.as_bytes(), key.as_bytes()).unwrap();
+
+    let server = TestServer::new()
+        .config(|server| server.get("/test", final_handler))
+        .start_tls(tls_config, &cert)
+        .await
+        .unwrap();
+
+    let res = server.get("/test", |req| req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.version(), reqwest::Version::HTTP_2);
+    assert_eq!(res.text().await.unwrap(), "HTTP/2.0");
+
+    let http1_client = reqwest::Client::builder()
+        .use_rustls_tls()
+        .tls_built_in_root_certs(false)
+        .add_root_certificate(reqwest::Certificate::from_pem(cert.as_bytes()).unwrap())
+        .http1_only()
+        .build()
+        .unwrap();
+    let res = http1_client
+        .get(format!("https://localhost:{}/test", server.port))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.version(), reqwest::Version::HTTP_11);
+    assert_eq!(res.text().await.unwrap(), "HTTP/1.1");
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_serve_unix() {
+    use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+
+    #[handler]
+    async fn final_handler(peer: PeerCredentials) -> String {
+        format!("{} {} {}", peer.uid, peer.gid, peer.pid.unwrap())
+    }
+
+    let path = temp_socket_path("serve-unix");
+    _ = std::fs::remove_file(&path);
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+
+    let (shutdown_sender, shutdown_receiver) = shutdown_signal();
+    let server = Server::new()
+        .get("/test", final_handler)
+        .with_unix_socket_permissions(0o600)
+        .with_graceful_shutdown(shutdown_receiver);
+    let serve_task = spawn({
+        let path = path.clone();
+        async move { server.serve_unix(path).await }
+    });
+
+    while tokio::net::UnixStream::connect(&path).await.is_err() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let metadata = std::fs::metadata(&path).unwrap();
+    assert!(metadata.file_type().is_socket());
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+
+    let (status, body) = unix_get(&path, "/test").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body,
+        format!(
+            "{} {} {}",
+            metadata.uid(),
+            metadata.gid(),
+            std::process::id()
+        )
+    );
+
+    let in_use = Server::new().serve_unix(&path).await.unwrap_err();
+    assert_eq!(in_use.kind(), io::ErrorKind::AddrInUse);
+
+    shutdown_sender.shutdown().await;
+    serve_task.await.unwrap().unwrap();
+    assert!(!path.exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_serve_unix_not_a_socket() {
+    let path = temp_socket_path("not-a-socket");
+    std::fs::write(&path, "important data").unwrap();
+
+    let err = Server::new().serve_unix(&path).await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "important data");
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_missing_peer_credentials() {
+    #[handler]
+    async fn final_handler(_: PeerCredentials) {}
+
+    let server = TestServer::new()
+        .config(|server| server.get("/test", final_handler))
+        .start()
+        .await
+        .unwrap();
+
+    let res = server.get("/test", |req| req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let errors = server.stop().await;
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(*errors[0], Error::MissingPeerCredentials));
+}
+
+#[tokio::test]
+async fn test_serve_memory() {
+    #[handler]
+    async fn final_handler(req: Request) -> String {
+        format!(
+            "{} {}",
+            req.peer_info().remote_addr(),
+            req.peer_info().local_addr()
+        )
+    }
+
+    let (listener, connector) = memory_listener();
+    assert_eq!(Listener::local_addr(&listener).unwrap(), Addr::Memory);
+
+    let (shutdown_sender, shutdown_receiver) = shutdown_signal();
+    let server = Server::new()
+        .get("/test", final_handler)
+        .with_graceful_shutdown(shutdown_receiver);
+    let serve_task = spawn(server.serve_with(listener));
+
+    let (status, body) = http1_get(connector.connect().unwrap(), "/test").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "memory memory");
+
+    shutdown_sender.shutdown().await;
+    serve_task.await.unwrap().unwrap();
+
+    let refused = connector.connect().unwrap_err();
+    assert_eq!(refused.kind(), io::ErrorKind::ConnectionRefused);
+}
+
+#[tokio::test]
+async fn test_custom_listener() {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct ConnectionNumber(usize);
+
+    struct CountingListener {
+        inner: TcpListener,
+        count: usize,
+    }
+
+    impl Listener for CountingListener {
+        type Io = tokio::net::TcpStream;
+
+        async fn accept(&mut self) -> io::Result<(Self::Io, PeerInfo)> {
+            let (conn, mut peer) = Listener::accept(&mut self.inner).await?;
+            self.count += 1;
+            peer.insert(ConnectionNumber(self.count));
+            Ok((conn, peer))
+        }
+
+        fn local_addr(&self) -> io::Result<Addr> {
+            Listener::local_addr(&self.inner)
+        }
+    }
+
+    #[handler]
+    async fn final_handler(req: Request) -> String {
+        let peer = req.peer_info();
+        let Addr::Tcp(remote_addr) = peer.remote_addr() else {
+            panic!("expected a TCP address");
+        };
+        assert!(remote_addr.ip().is_loopback());
+        assert!(matches!(peer.local_addr(), Addr::Tcp(addr) if addr.ip().is_loopback()));
+
+        peer.get::<ConnectionNumber>().unwrap().0.to_string()
+    }
+
+    let listener = CountingListener {
+        inner: TcpListener::bind("127.0.0.1:0").await.unwrap(),
+        count: 0,
+    };
+    let Addr::Tcp(addr) = Listener::local_addr(&listener).unwrap() else {
+        panic!("expected a TCP address");
+    };
+
+    let (shutdown_sender, shutdown_receiver) = shutdown_signal();
+    let server = Server::new()
+        .get("/test", final_handler)
+        .with_graceful_shutdown(shutdown_receiver);
+    let serve_task = spawn(server.serve_with(listener));
+
+    for expected in ["1", "2"] {
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (status, body) = http1_get(stream, "/test").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, expected);
+    }
+
+    shutdown_sender.shutdown().await;
+    serve_task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_graceful_shutdown_drains_connections() {
+    #[handler]
+    async fn final_handler() -> &'static str {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        "done"
+    }
+
+    let (listener, connector) = memory_listener();
+    let (shutdown_sender, shutdown_receiver) = shutdown_signal();
+    let server = Server::new()
+        .get("/test", final_handler)
+        .with_graceful_shutdown(shutdown_receiver);
+    let serve_task = spawn(server.serve_with(listener));
+
+    let idle_conn = connector.connect().unwrap();
+    let in_flight = spawn(http1_get(connector.connect().unwrap(), "/test"));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let start = Instant::now();
+    shutdown_sender.shutdown().await;
+
+    let (status, body) = in_flight.await.unwrap();
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "done");
+
+    let report = serve_task.await.unwrap().unwrap();
+    assert_eq!(
+        report,
+        ShutdownReport {
+            drained: 2,
+            force_closed: 0,
+        }
+    );
+    assert!(start.elapsed() < Duration::from_secs(5));
+    drop(idle_conn);
+}
+
+#[tokio::test]
+async fn test_graceful_shutdown_timeout() {
+    #[handler]
+    async fn final_handler() -> &'static str {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        "done"
+    }
+
+    let (listener, connector) = memory_listener();
+    let (shutdown_sender, shutdown_receiver) = shutdown_signal();
+    let server = Server::new()
+        .get("/test", final_handler)
+        .with_graceful_shutdown(shutdown_receiver)
+        .with_shutdown_timeout(Duration::from_millis(200));
+    let serve_task = spawn(server.serve_with(listener));
+
+    let in_flight = spawn(http1_get(connector.connect().unwrap(), "/test"));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let start = Instant::now();
+    shutdown_sender.shutdown().await;
+
+    let report = serve_task.await.unwrap().unwrap();
+    assert_eq!(
+        report,
+        ShutdownReport {
+            drained: 0,
+            force_closed: 1,
+        }
+    );
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(in_flight.await.is_err());
+}
+
+#[tokio::test]
+async fn test_shutdown_zero_timeout() {
+    #[handler]
+    async fn final_handler() -> &'static str {
+        "done"
+    }
+
+    let server = Server::new()
+        .get("/test", final_handler)
+        .with_shutdown_timeout(Duration::ZERO)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap();
+    let Addr::Tcp(addr) = *server.local_addr() else {
+        panic!("expected a TCP address");
+    };
+
+    // Connections that have already finished are not counted, even while
+    // they are still waiting to be cleaned up.
+    for _ in 0..200 {
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (status, _) = http1_get(stream, "/test").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    while server.connections() != 0 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert_eq!(server.shutdown().await, ShutdownReport::default());
+}
+
+#[tokio::test]
+async fn test_shutdown_sender_clone() {
+    let (shutdown_sender, shutdown_receiver) = shutdown_signal();
+    let other_shutdown_sender = shutdown_sender.clone();
+
+    let (listener, _connector) = memory_listener();
+    let server = Server::new().with_graceful_shutdown(shutdown_receiver);
+    let serve_task = spawn(server.serve_with(listener));
+
+    assert!(other_shutdown_sender.shutdown().await);
+    serve_task.await.unwrap().unwrap();
+    assert!(!shutdown_sender.shutdown().await);
+
+    let (shutdown_sender, mut shutdown_receiver) = shutdown_signal();
+    drop(shutdown_sender);
+    assert!(!shutdown_receiver.await_signal().await);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_signals() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn send_signal(name: &str) {
+        let status = std::process::Command::new("kill")
+            .args([&format!("-{}", name), &std::process::id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    let reloads = Arc::new(AtomicUsize::new(0));
+    let (shutdown_sender, shutdown_receiver) = shutdown_signal();
+    let signal_task = Signals::new()
+        .with_shutdown(shutdown_sender)
+        .with_force_exit_on_repeat(false)
+        .on_reload({
+            let reloads = Arc::clone(&reloads);
+            move || {
+                reloads.fetch_add(1, Ordering::SeqCst);
+            }
+        })
+        .listen()
+        .unwrap();
+
+    let (listener, _connector) = memory_listener();
+    let server = Server::new().with_graceful_shutdown(shutdown_receiver);
+    let serve_task = spawn(server.serve_with(listener));
+
+    send_signal("HUP");
+    while reloads.load(Ordering::SeqCst) == 0 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(!serve_task.is_finished());
+
+    send_signal("TERM");
+    tokio::time::timeout(Duration::from_secs(5), serve_task)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(reloads.load(Ordering::SeqCst), 1);
+
+    // Repeated shutdown signals are ignored when forced exits are disabled.
+    send_signal("TERM");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!signal_task.is_finished());
+
+    signal_task.abort();
+}
+
+#[tokio::test]
+async fn test_running_server() {
+    #[handler]
+    async fn final_handler() -> &'static str {
+        "Hello, running server!"
+    }
+
+    let server = Server::new()
+        .get("/test", final_handler)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap();
+    let Addr::Tcp(addr) = *server.local_addr() else {
+        panic!("expected a TCP address");
+    };
+    assert_ne!(addr.port(), 0);
+    assert_eq!(server.connections(), 0);
+
+    let idle_conn = tokio::net::TcpStream::connect(addr).await.unwrap();
+    while server.connections() != 1 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let (status, body) = http1_get(stream, "/test").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "Hello, running server!");
+    while server.connections() != 1 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let report = server.shutdown().await;
+    assert_eq!(
+        report,
+        ShutdownReport {
+            drained: 1,
+            force_closed: 0,
+        }
+    );
+    drop(idle_conn);
+
+    let (shutdown_sender, shutdown_receiver) = shutdown_signal();
+    let (listener, _connector) = memory_listener();
+    let server = Server::new()
+        .with_graceful_shutdown(shutdown_receiver)
+        .bind_with(listener)
+        .await
+        .unwrap();
+    assert_eq!(server.local_addr(), &Addr::Memory);
+
+    shutdown_sender.shutdown().await;
+    assert_eq!(server.join().await, ShutdownReport::default());
+}
+
+#[tokio::test]
+async fn test_lifecycle_hooks() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[derive(Clone)]
+    struct Greeting(String);
+
+    #[derive(Clone)]
+    struct Flushed(Arc<AtomicBool>);
+
+    #[handler]
+    async fn final_handler(greeting: State<Greeting>) -> String {
+        greeting.0 .0.clone()
+    }
+
+    let flushed = Arc::new(AtomicBool::new(false));
+    let server = Server::new()
+        .get("/test", final_handler)
+        .with_state(Flushed(Arc::clone(&flushed)))
+        .on_startup(|state| async move {
+            tokio::task::yield_now().await;
+            state.insert(Greeting("Hello, startup!".to_owned()));
+            Ok::<_, Infallible>(())
+        })
+        .on_shutdown(|state| async move {
+            assert_eq!(state.get::<Greeting>().unwrap().0, "Hello, startup!");
+            state
+                .get::<Flushed>()
+                .unwrap()
+                .0
+                .store(true, Ordering::SeqCst);
+        })
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap();
+    let Addr::Tcp(addr) = *server.local_addr() else {
+        panic!("expected a TCP address");
+    };
+
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let (status, body) = http1_get(stream, "/test").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "Hello, startup!");
+    assert!(!flushed.load(Ordering::SeqCst));
+
+    server.shutdown().await;
+    assert!(flushed.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn test_failing_startup_hook() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let later_hook_ran = Arc::new(AtomicBool::new(false));
+    let (listener, _connector) = memory_listener();
+    let err = Server::new()
+        .on_startup(|_| async { Err("database unavailable") })
+        .on_startup({
+            let later_hook_ran = Arc::clone(&later_hook_ran);
+            move |_| async move {
+                later_hook_ran.store(true, Ordering::SeqCst);
+                Ok::<_, Infallible>(())
+            }
+        })
+        .serve_with(listener)
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "database unavailable");
+    assert!(!later_hook_ran.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn test_max_body_size() {
+    #[handler]
+    async fn final_handler(body: BodyRaw) -> String {
+        body.len().to_string()
+    }
+
+    let server = TestServer::new()
+        .config(|server| {
+            server
+                .post("/test", final_handler)
+                .with_max_body_size(16)
+                .route_group(
+                    RouteGroup::new("/group")
+                        .post("/small", final_handler)
+                        .post(
+                            "/large",
+                            RouteHandler::from(final_handler)
+                                .with_config(RouteConfig::new().with_max_body_size(64)),
+                        )
+                        .with_config(RouteConfig::new().with_max_body_size(4)),
+                )
+        })
+        .start()
+        .await
+        .unwrap();
+
+    for (path, len, status) in [
+        ("/test", 16, StatusCode::OK),
+        ("/test", 17, StatusCode::PAYLOAD_TOO_LARGE),
+        ("/group/small", 4, StatusCode::OK),
+        ("/group/small", 5, StatusCode::PAYLOAD_TOO_LARGE),
+        ("/group/large", 64, StatusCode::OK),
+        ("/group/large", 65, StatusCode::PAYLOAD_TOO_LARGE),
+    ] {
+        let res = server
+            .post(path, |req| req.body("a".repeat(len)))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), status);
+
+        if status == StatusCode::OK {
+            assert_eq!(res.text().await.unwrap(), len.to_string());
+        }
+    }
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_max_body_size_streaming() {
+    use tokio::io::AsyncWriteExt;
+
+    #[handler]
+    async fn final_handler(body: BodyRaw) -> String {
+        body.len().to_string()
+    }
+
+    let server = Server::new()
+        .post("/test", final_handler)
+        .with_max_body_size(16)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap();
+    let Addr::Tcp(addr) = *server.local_addr() else {
+        panic!("expected a TCP address");
+    };
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(
+            b"POST /test HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n\
+              a\r\naaaaaaaaaa\r\na\r\naaaaaaaaaa\r\n0\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    let head = read_response_head(&mut stream).await;
+    assert!(head.starts_with("HTTP/1.1 413 "), "{}", head);
+
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_max_body_size_expect_continue() {
+    use tokio::io::AsyncWriteExt;
+
+    #[handler]
+    async fn final_handler(body: BodyRaw) -> String {
+        body.len().to_string()
+    }
+
+    let server = Server::new()
+        .post("/test", final_handler)
+        .with_max_body_size(16)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap();
+    let Addr::Tcp(addr) = *server.local_addr() else {
+        panic!("expected a TCP address");
+    };
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(
+            b"POST /test HTTP/1.1\r\nHost: localhost\r\nContent-Length: 17\r\n\
+              Expect: 100-continue\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    let head = read_response_head(&mut stream).await;
+    assert!(head.starts_with("HTTP/1.1 413 "), "{}", head);
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(
+            b"POST /test HTTP/1.1\r\nHost: localhost\r\nContent-Length: 16\r\n\
+              Expect: 100-continue\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    let head = read_response_head(&mut stream).await;
+    assert!(head.starts_with("HTTP/1.1 100 Continue"), "{}", head);
+
+    stream.write_all(&[b'a'; 16]).await.unwrap();
+    let head = read_response_head(&mut stream).await;
+    assert!(head.starts_with("HTTP/1.1 200 OK"), "{}", head);
+
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_handler_timeout() {
+    #[handler]
+    async fn final_handler() -> &'static str {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        "done"
+    }
+
+    let server = TestServer::new()
+        .config(|server| {
+            server
+                .get("/test", final_handler)
+                .with_handler_timeout(Duration::from_millis(100))
+                .route_group(
+                    RouteGroup::new("/group")
+                        .get("/test", final_handler)
+                        .with_config(
+                            RouteConfig::new().with_handler_timeout(Duration::from_secs(5)),
+                        ),
+                )
+        })
+        .start()
+        .await
+        .unwrap();
+
+    let res = server.get("/test", |req| req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let res = server.get("/group/test", |req| req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "done");
+
+    let errors = server.stop().await;
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(*errors[0], Error::HandlerTimeout));
+}
+
+#[tokio::test]
+async fn test_read_timeouts() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[handler]
+    async fn final_handler(body: BodyRaw) -> String {
+        body.len().to_string()
+    }
+
+    let server = Server::new()
+        .post("/test", final_handler)
+        .with_header_read_timeout(Duration::from_millis(100))
+        .with_body_read_timeout(Duration::from_millis(100))
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap();
+    let Addr::Tcp(addr) = *server.local_addr() else {
+        panic!("expected a TCP address");
+    };
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"POST /test HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\naaaaa")
+        .await
+        .unwrap();
+    let head = read_response_head(&mut stream).await;
+    assert!(head.starts_with("HTTP/1.1 408 "), "{}", head);
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"POST /test HTTP/1.1\r\nHost: localhost\r\n")
+        .await
+        .unwrap();
+    let mut buf = Vec::new();
+    let read = tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut buf))
+        .await
+        .unwrap();
+    assert!(read.is_err() || buf.is_empty());
+
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_extract_body_stream() {
+    use futures_util::StreamExt;
+
+    #[handler]
+    async fn final_handler(mut body: BodyStream) -> Result<String> {
+        let mut len = 0;
+
+        while let Some(chunk) = body.next().await {
+            len += chunk?.len();
+        }
+
+        Ok(len.to_string())
+    }
+
+    #[handler]
+    async fn buffered_handler(_body: BodyStream) -> &'static str {
+        unreachable!()
+    }
+
+    let server = TestServer::new()
+        .config(|server| {
+            server
+                .post(
+                    "/test",
+                    RouteHandler::from(final_handler)
+                        .with_config(RouteConfig::new().with_streaming_body(true)),
+                )
+                .post("/buffered", buffered_handler)
+                .route_group(
+                    RouteGroup::new("/group")
+                        .post("/test", final_handler)
+                        .with_config(
+                            RouteConfig::new()
+                                .with_streaming_body(true)
+                                .with_max_body_size(16),
+                        ),
+                )
+        })
+        .start()
+        .await
+        .unwrap();
+
+    let res = server
+        .post("/test", |req| req.body(vec![0u8; 4 * 1024 * 1024]))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let res = server
+        .post("/test", |req| req.body(vec![0u8; 1024 * 1024]))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), (1024 * 1024).to_string());
+
+    let res = server
+        .post("/group/test", |req| req.body("a".repeat(16)))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "16");
+
+    let res = server.post("/buffered", |req| req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let errors = server.stop().await;
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(*errors[0], Error::BodyStreamUnavailable));
+}
+
+#[tokio::test]
+async fn test_body_stream_limit() {
+    use tokio::io::AsyncWriteExt;
+
+    #[handler]
+    async fn final_handler(mut body: BodyStream) -> Result<String> {
+        let mut len = 0;
+
+        while let Some(chunk) = body.next_chunk().await {
+            len += chunk?.len();
+        }
+
+        Ok(len.to_string())
+    }
+
+    #[handler]
+    async fn slow_handler(mut body: BodyStream) -> Result<String> {
+        // The read timeout only starts once the stream is polled.
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let mut len = 0;
+
+        while let Some(chunk) = body.next_chunk().await {
+            len += chunk?.len();
+        }
+
+        Ok(len.to_string())
+    }
+
+    let server = Server::new()
+        .post(
+            "/test",
+            RouteHandler::from(final_handler).with_config(
+                RouteConfig::new()
+                    .with_streaming_body(true)
+                    .with_max_body_size(16),
+            ),
+        )
+        .post(
+            "/slow",
+            RouteHandler::from(slow_handler).with_config(
+                RouteConfig::new()
+                    .with_streaming_body(true)
+                    .with_body_read_timeout(Duration::from_millis(50)),
+            ),
+        )
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap();
+    let Addr::Tcp(addr) = *server.local_addr() else {
+        panic!("expected a TCP address");
+    };
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(
+            b"POST /test HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n\
+              a\r\naaaaaaaaaa\r\na\r\naaaaaaaaaa\r\n0\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    let head = read_response_head(&mut stream).await;
+    assert!(head.starts_with("HTTP/1.1 413 "), "{}", head);
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"POST /slow HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\n\r\nbody")
+        .await
+        .unwrap();
+    let head = read_response_head(&mut stream).await;
+    assert!(head.starts_with("HTTP/1.1 200 "), "{}", head);
+
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_response_bytes() {
+    #[handler]
+    async fn bytes_handler() -> Vec<u8> {
+        vec![0, 159, 146, 150]
+    }
+
+    #[handler]
+    async fn raw_handler(body: BodyRaw) -> BodyRaw {
+        body
+    }
+
+    #[handler]
+    async fn builder_handler() -> Response {
+        Response::new()
+            .body_bytes(Bytes::from_static(&[1, 2, 3]))
+            .header("Content-Type", "image/png")
+    }
+
+    let server = TestServer::new()
+        .config(|server| {
+            server
+                .get("/bytes", bytes_handler)
+                .post("/raw", raw_handler)
+                .get("/builder", builder_handler)
+        })
+        .start()
+        .await
+        .unwrap();
+
+    let res = server.get("/bytes", |req| req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers().get("Content-Type").unwrap(),
+        "application/octet-stream"
+    );
+    assert_eq!(res.headers().get("Content-Length").unwrap(), "4");
+    assert_eq!(res.bytes().await.unwrap().as_ref(), &[0, 159, 146, 150]);
+
+    let res = server
+        .post("/raw", |req| req.body(vec![255, 0, 255]))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.bytes().await.unwrap().as_ref(), &[255, 0, 255]);
+
+    let res = server.get("/builder", |req| req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get("Content-Type").unwrap(), "image/png");
+    assert_eq!(res.bytes().await.unwrap().as_ref(), &[1, 2, 3]);
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_response_stream() {
+    use futures_util::stream;
+
+    #[handler]
+    async fn stream_handler() -> StreamBody {
+        StreamBody::new(stream::unfold(0, |i| async move {
+            if i == 3 {
+                return None;
+            }
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Some((Ok::<_, Infallible>(format!("chunk {}\n", i)), i + 1))
+        }))
+    }
+
+    #[handler]
+    async fn failing_handler() -> Response {
+        Response::new()
+            .body_stream(StreamBody::new(stream::iter([
+                Ok(Bytes::from_static(b"partial")),
+                Err(io::Error::other("stream failed")),
+            ])))
+            .header("Content-Type", "text/plain")
+    }
+
+    let server = TestServer::new()
+        .config(|server| {
+            server
+                .get("/stream", stream_handler)
+                .get("/failing", failing_handler)
+        })
+        .start()
+        .await
+        .unwrap();
+
+    let res = server.get("/stream", |req| req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get("Transfer-Encoding").unwrap(), "chunked");
+    assert!(res.headers().get("Content-Length").is_none());
+    assert_eq!(res.text().await.unwrap(), "chunk 0\nchunk 1\nchunk 2\n");
+
+    // The response is aborted, either before or after the head is sent.
+    match server.get("/failing", |req| req).await {
+        Ok(res) => assert!(res.bytes().await.is_err()),
+        Err(err) => assert!(err.is_request()),
+    }
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_sse() {
+    use futures_util::stream;
+
+    #[handler]
+    async fn events_handler(last_event_id: LastEventId) -> Sse {
+        let start = last_event_id
+            .as_deref()
+            .map(|id| id.parse::<usize>().unwrap() + 1)
+            .unwrap_or(0);
+
+        Sse::new(stream::iter((start..3).map(|i| {
+            Ok::<_, Infallible>(
+                Event::new()
+                    .with_id(i.to_string())
+                    .with_event("count")
+                    .with_data(format!("line {}\nline {}", i, i + 1)),
+            )
+        })))
+    }
+
+    #[handler]
+    async fn fields_handler() -> Sse {
+        Sse::new(stream::iter([Ok::<_, Infallible>(
+            Event::new()
+                .with_comment("hello")
+                .with_id("a\nb")
+                .with_retry(Duration::from_secs(3))
+                .with_json_data(&HashMap::from([("num", 123)]))
+                .unwrap(),
+        )]))
+        .without_keep_alive()
+    }
+
+    #[handler]
+    async fn idle_handler() -> Sse {
+        Sse::new(stream::pending::<Result<Event>>()).with_keep_alive(Duration::from_millis(20))
+    }
+
+    let server = TestServer::new()
+        .config(|server| {
+            server
+                .get("/events", events_handler)
+                .get("/fields", fields_handler)
+                .get("/idle", idle_handler)
+        })
+        .start()
+        .await
+        .unwrap();
+
+    let res = server.get("/events", |req| req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers().get("Content-Type").unwrap(),
+        "text/event-stream"
+    );
+    assert_eq!(res.headers().get("Cache-Control").unwrap(), "no-cache");
+    assert_eq!(
+        res.text().await.unwrap(),
+        "id: 0\nevent: count\ndata: line 0\ndata: line 1\n\n\
+         id: 1\nevent: count\ndata: line 1\ndata: line 2\n\n\
+         id: 2\nevent: count\ndata: line 2\ndata: line 3\n\n"
+    );
+
+    let res = server
+        .get("/events", |req| req.header("Last-Event-ID", "1"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.text().await.unwrap(),
+        "id: 2\nevent: count\ndata: line 2\ndata: line 3\n\n"
+    );
+
+    let res = server.get("/fields", |req| req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.text().await.unwrap(),
+        ": hello\nid: ab\nretry: 3000\ndata: {\"num\":123}\n\n"
+    );
+
+    let mut res = server.get("/idle", |req| req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.chunk().await.unwrap().unwrap().as_ref(), b":\n\n");
+    assert_eq!(res.chunk().await.unwrap().unwrap().as_ref(), b":\n\n");
+    drop(res);
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_websocket() {
+    use futures_util::{SinkExt, StreamExt};
+    use rum::websocket::Message;
+    use tokio::io::AsyncWriteExt;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::{self, Message as ClientMessage};
+
+    #[middleware]
+    async fn auth(req: Request, next: NextFn) -> Response {
+        match req.header_optional("Authorization") {
+            Some(_) => next.call(req).await,
+            None => Response::new().status_code(StatusCode::UNAUTHORIZED),
+        }
+    }
+
+    #[handler]
+    async fn echo(upgrade: WebSocketUpgrade) -> Response {
+        upgrade
+            .with_protocols(["chat.v2", "chat"])
+            .with_max_message_size(1024)
+            .on_upgrade(|mut socket| async move {
+                while let Some(Ok(message)) = socket.recv().await {
+                    let reply = match message {
+                        Message::Text(text) => {
+                            Message::Text(format!("{}:{}", socket.protocol().unwrap(), text))
+                        }
+                        Message::Binary(data) => Message::Binary(data),
+                        _ => continue,
+                    };
+
+                    socket.send(reply).await.unwrap();
+                }
+            })
+    }
+
+    let server = TestServer::new()
+        .config(|server| {
+            server.route_group(
+                RouteGroup::new("/ws")
+                    .get("/echo", echo)
+                    .with_middleware(auth),
+            )
+        })
+        .start()
+        .await
+        .unwrap();
+    let url = format!("ws://127.0.0.1:{}/ws/echo", server.port);
+
+    let connect = |auth: bool| {
+        let mut req = url.as_str().into_client_request().unwrap();
+        req.headers_mut()
+            .insert("Sec-WebSocket-Protocol", "chat,chat.v2".parse().unwrap());
+
+        if auth {
+            req.headers_mut()
+                .insert("Authorization", "token".parse().unwrap());
+        }
+
+        async move {
+            let stream = tokio::net::TcpStream::connect(("127.0.0.1", server.port))
+                .await
+                .unwrap();
+            tokio_tungstenite::client_async(req, stream).await
+        }
+    };
+
+    let (mut socket, res) = connect(true).await.unwrap();
+    assert_eq!(
+        res.headers().get("Sec-WebSocket-Protocol").unwrap(),
+        "chat.v2"
+    );
+
+    socket.send(ClientMessage::text("hello")).await.unwrap();
+    assert_eq!(
+        socket.next().await.unwrap().unwrap(),
+        ClientMessage::text("chat.v2:hello")
+    );
+
+    socket
+        .send(ClientMessage::binary(vec![1, 2, 3]))
+        .await
+        .unwrap();
+    assert_eq!(
+        socket.next().await.unwrap().unwrap(),
+        ClientMessage::binary(vec![1, 2, 3])
+    );
+
+    socket
+        .send(ClientMessage::text("a".repeat(2048)))
+        .await
+        .unwrap();
+    assert!(!matches!(
+        socket.next().await,
+        Some(Ok(ClientMessage::Text(_)))
+    ));
+
+    match connect(false).await {
+        Err(tungstenite::Error::Http(res)) => assert_eq!(res.status(), StatusCode::UNAUTHORIZED),
+        res => panic!("expected an HTTP error, got {:?}", res),
+    }
+
+    let res = server
+        .get("/ws/echo", |req| req.header("Authorization", "token"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", server.port))
+        .await
+        .unwrap();
+    stream
+        .write_all(
+            b"GET /ws/echo HTTP/1.1\r\nHost: localhost\r\nAuthorization: token\r\n\
+              Connection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Version: 8\r\n\
+              Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    let head = read_response_head(&mut stream).await;
+    assert!(head.starts_with("HTTP/1.1 426 "), "{}", head);
+    assert!(head.contains("sec-websocket-version: 13\r\n"), "{}", head);
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_upgrade() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn echo(upgrade: OnUpgrade) {
+        let upgraded = upgrade.await.unwrap();
+        let (mut reader, mut writer) = tokio::io::split(upgraded);
+        tokio::io::copy(&mut reader, &mut writer).await.unwrap();
+    }
+
+    #[handler]
+    async fn upgrade_handler(upgrade: OnUpgrade) -> Response {
+        spawn(echo(upgrade));
+
+        Response::new()
+            .status_code(StatusCode::SWITCHING_PROTOCOLS)
+            .header("Connection", "upgrade")
+            .header("Upgrade", "echo")
+    }
+
+    #[handler]
+    async fn connect_handler(req: Request, upgrade: OnUpgrade) -> StatusCode {
+        assert_eq!(req.authority(), Some("example.com:443"));
+        spawn(echo(upgrade));
+        StatusCode::OK
+    }
+
+    let server = TestServer::new()
+        .config(|server| {
+            server
+                .get("/upgrade", upgrade_handler)
+                .connect("/", connect_handler)
+        })
+        .start()
+        .await
+        .unwrap();
+
+    let requests: [&[u8]; 2] = [
+        b"GET /upgrade HTTP/1.1\r\nHost: localhost\r\nConnection: upgrade\r\nUpgrade: echo\r\n\r\n",
+        b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n",
+    ];
+    let statuses = ["HTTP/1.1 101 ", "HTTP/1.1 200 "];
+
+    for (request, status) in requests.into_iter().zip(statuses) {
+        let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", server.port))
+            .await
+            .unwrap();
+        stream.write_all(request).await.unwrap();
+        let head = read_response_head(&mut stream).await;
+        assert!(head.starts_with(status), "{}", head);
+
+        stream.write_all(b"raw bytes").await.unwrap();
+        let mut buf = [0; 9];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"raw bytes");
+    }
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_hub() {
+    use futures_util::StreamExt;
+
+    let hub = Hub::<u32>::new().with_capacity(2);
+    assert_eq!(hub.publish("numbers", 0), 0);
+
+    let mut sub1 = hub.subscribe("numbers");
+    let mut sub2 = hub.clone().subscribe("numbers");
+    assert_eq!(sub1.channel(), "numbers");
+    assert_eq!(hub.presence("numbers"), 2);
+    assert_eq!(hub.presence("other"), 0);
+    assert_eq!(hub.channels(), vec!["numbers".to_owned()]);
+
+    assert_eq!(hub.publish("numbers", 1), 2);
+    assert_eq!(sub1.recv().await, Some(1));
+    assert_eq!(sub2.next().await, Some(1));
+
+    for i in 2..6 {
+        hub.publish("numbers", i);
+    }
+
+    assert_eq!(sub1.recv().await, Some(4));
+    assert_eq!(sub1.recv().await, Some(5));
+    assert_eq!(sub1.missed(), 2);
+
+    drop(sub1);
+    assert_eq!(hub.presence("numbers"), 1);
+    drop(sub2);
+    assert_eq!(hub.presence("numbers"), 0);
+    assert!(hub.channels().is_empty());
+
+    let hub = Hub::<u32>::new()
+        .with_capacity(2)
+        .with_lag_policy(LagPolicy::Disconnect);
+    let mut sub = hub.subscribe("numbers");
+
+    for i in 0..3 {
+        hub.publish("numbers", i);
+    }
+
+    assert_eq!(sub.recv().await, None);
+    assert_eq!(hub.presence("numbers"), 0);
+    assert!(hub.channels().is_empty());
+}
+
+#[tokio::test]
+async fn test_hub_responses() {
+    use futures_util::StreamExt;
+
+    #[handler]
+    async fn long_poll(hub: State<Hub<String>>) -> String {
+        hub.subscribe("news").recv().await.unwrap()
+    }
+
+    #[handler]
+    async fn events(hub: State<Hub<String>>) -> Sse {
+        Sse::new(
+            hub.subscribe("news")
+                .map(|message| Ok::<_, Infallible>(Event::new().with_data(message))),
+        )
+    }
+
+    let hub = Hub::<String>::new();
+    let server = TestServer::new()
+        .config(|server| {
+            server
+                .with_state(hub.clone())
+                .get("/poll", long_poll)
+                .get("/events", events)
+        })
+        .start()
+        .await
+        .unwrap();
+
+    let wait_for_subscribers = |count: usize| {
+        let hub = hub.clone();
+
+        async move {
+            while hub.presence("news") != count {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        }
+    };
+
+    let (poll_res, events_res, ()) = tokio::join!(
+        server.get("/poll", |req| req),
+        server.get("/events", |req| req),
+        async {
+            wait_for_subscribers(2).await;
+            assert_eq!(hub.publish("news", "hello".to_owned()), 2);
+        }
+    );
+
+    let poll_res = poll_res.unwrap();
+    assert_eq!(poll_res.status(), StatusCode::OK);
+    assert_eq!(poll_res.text().await.unwrap(), "hello");
+
+    let mut res = events_res.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.chunk().await.unwrap().unwrap().as_ref(),
+        b"data: hello\n\n"
+    );
+
+    drop(res);
+    wait_for_subscribers(0).await;
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_connect_info() {
+    #[derive(Serialize, Deserialize)]
+    struct Info {
+        remote_ip: String,
+        local_port: u16,
+        connection_id: u64,
+        request_count: u64,
+        protocol: String,
+    }
+
+    #[handler]
+    async fn info_handler(info: ConnectInfo) -> Json<Info> {
+        let (Addr::Tcp(remote_addr), Addr::Tcp(local_addr)) = (info.remote_addr, info.local_addr)
+        else {
+            panic!("expected TCP addresses");
+        };
+
+        Json(Info {
+            remote_ip: remote_addr.ip().to_string(),
+            local_port: local_addr.port(),
+            connection_id: info.connection_id,
+            request_count: info.request_count,
+            protocol: format!("{:?}", info.protocol),
+        })
+    }
+
+    let server = TestServer::new()
+        .config(|server| server.get("/info", info_handler))
+        .start()
+        .await
+        .unwrap();
+
+    let first = server.get_as::<Info, _>("/info", |req| req).await.unwrap();
+    assert_eq!(first.remote_ip, "127.0.0.1");
+    assert_eq!(first.local_port, server.port);
+    assert_eq!(first.request_count, 1);
+    assert_eq!(first.protocol, "HTTP/1.1");
+
+    let second = server.get_as::<Info, _>("/info", |req| req).await.unwrap();
+    assert_eq!(second.connection_id, first.connection_id);
+    assert_eq!(second.request_count, 2);
+
+    let client = reqwest::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    let other = client
+        .get(format!("http://127.0.0.1:{}/info", server.port))
+        .send()
+        .await
+        .unwrap()
+        .json::<Info>()
+        .await
+        .unwrap();
+    assert_ne!(other.connection_id, first.connection_id);
+    assert_eq!(other.request_count, 1);
+    assert_eq!(other.protocol, "HTTP/2.0");
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[test]
+fn test_ip_cidr() {
+    let cidr = "10.1.2.3/8".parse::<IpCidr>().unwrap();
+    assert_eq!(cidr.to_string(), "10.0.0.0/8");
+    assert!(cidr.contains("10.255.0.1".parse().unwrap()));
+    assert!(cidr.contains("::ffff:10.0.0.1".parse().unwrap()));
+    assert!(!cidr.contains("11.0.0.1".parse().unwrap()));
+
+    let cidr = "2001:db8::/32".parse::<IpCidr>().unwrap();
+    assert!(cidr.contains("2001:db8:1::1".parse().unwrap()));
+    assert!(!cidr.contains("2001:db9::1".parse().unwrap()));
+    assert!(!cidr.contains("10.0.0.1".parse().unwrap()));
+
+    let cidr = "192.0.2.1".parse::<IpCidr>().unwrap();
+    assert_eq!(cidr.prefix_len(), 32);
+    assert!(cidr.contains("192.0.2.1".parse().unwrap()));
+    assert!(!cidr.contains("192.0.2.2".parse().unwrap()));
+
+    let cidr = IpCidr::from("::ffff:192.0.2.1".parse::<std::net::IpAddr>().unwrap());
+    assert_eq!(cidr.to_string(), "192.0.2.1/32");
+    assert!(cidr.contains("192.0.2.1".parse().unwrap()));
+    assert!(cidr.contains("::ffff:192.0.2.1".parse().unwrap()));
+
+    let cidr = "::ffff:10.1.0.0/104".parse::<IpCidr>().unwrap();
+    assert_eq!(cidr.to_string(), "10.0.0.0/8");
+    assert!(cidr.contains("10.2.3.4".parse().unwrap()));
+
+    let cidr = "::/64".parse::<IpCidr>().unwrap();
+    assert!(cidr.contains("::ffff:10.0.0.1".parse().unwrap()));
+    assert!(cidr.contains("10.0.0.1".parse().unwrap()));
+
+    assert!("0.0.0.0/0"
+        .parse::<IpCidr>()
+        .unwrap()
+        .contains("1.2.3.4".parse().unwrap()));
+
+    for invalid in ["10.0.0.0/33", "10.0.0.0/", "not an ip", "::/129"] {
+        assert!(matches!(
+            invalid.parse::<IpCidr>(),
+            Err(Error::InvalidCidr(s)) if s == invalid
+        ));
+    }
+}
+
+#[tokio::test]
+async fn test_client_ip() {
+    #[handler]
+    async fn client_handler(ip: ClientIp, origin: RequestOrigin) -> String {
+        format!("{} {}", *ip, origin.base_url().unwrap())
+    }
+
+    async fn trusted_server(header: ForwardedHeader) -> TestServerHandle {
+        TestServer::new()
+            .config(|server| {
+                server
+                    .with_trusted_proxies(
+                        TrustedProxies::from_iter([
+                            "127.0.0.0/8".parse().unwrap(),
+                            "10.0.0.0/8".parse().unwrap(),
+                        ])
+                        .with_header(header),
+                    )
+                    .get("/client", client_handler)
+            })
+            .start()
+            .await
+            .unwrap()
+    }
+
+    let forwarded_for = trusted_server(ForwardedHeader::XForwardedFor).await;
+    let forwarded = trusted_server(ForwardedHeader::Forwarded).await;
+    let real_ip = trusted_server(ForwardedHeader::XRealIp).await;
+    let untrusted = TestServer::new()
+        .config(|server| server.get("/client", client_handler))
+        .start()
+        .await
+        .unwrap();
+
+    // The trusted server, the request headers, and the expected client IP
+    // and origin.
+    type Case<'a> = (
+        &'a TestServerHandle,
+        &'static [(&'static str, &'static str)],
+        &'static str,
+        &'static str,
+    );
+
+    let cases: [Case; 10] = [
+        (&forwarded_for, &[], "127.0.0.1", "http://localhost:{port}"),
+        (
+            &forwarded_for,
+            &[("X-Forwarded-For", "203.0.113.7, 10.0.0.1")],
+            "203.0.113.7",
+            "http://localhost:{port}",
+        ),
+        (
+            &forwarded_for,
+            &[
+                (
+                    "X-Forwarded-For",
+                    "198.51.100.1, 203.0.113.7:4000, 10.0.0.1",
+                ),
+                ("X-Forwarded-Proto", "https"),
+                ("X-Forwarded-Host", "example.com"),
+            ],
+            "203.0.113.7",
+            "https://example.com",
+        ),
+        // Leading values supplied by the client are ignored.
+        (
+            &forwarded_for,
+            &[
+                ("X-Forwarded-For", "203.0.113.7"),
+                ("X-Forwarded-Proto", "https, http"),
+                ("X-Forwarded-Host", "evil.example, example.com"),
+            ],
+            "203.0.113.7",
+            "http://example.com",
+        ),
+        // Headers other than the configured one are ignored.
+        (
+            &forwarded_for,
+            &[
+                ("Forwarded", "for=192.0.2.60;proto=https;host=example.org"),
+                ("X-Forwarded-For", "203.0.113.7"),
+            ],
+            "203.0.113.7",
+            "http://localhost:{port}",
+        ),
+        (
+            &forwarded_for,
+            &[("X-Real-IP", "198.51.100.9")],
+            "127.0.0.1",
+            "http://localhost:{port}",
+        ),
+        (
+            &forwarded,
+            &[
+                (
+                    "Forwarded",
+                    "for=192.0.2.60;proto=http;host=evil.example, \
+                     for=\"[2001:db8::1]:8080\";proto=https;host=example.org",
+                ),
+                ("X-Forwarded-For", "203.0.113.7"),
+            ],
+            "2001:db8::1",
+            "https://example.org",
+        ),
+        (
+            &forwarded,
+            &[("Forwarded", "for=unknown, for=10.0.0.2")],
+            "10.0.0.2",
+            "http://localhost:{port}",
+        ),
+        (
+            &forwarded,
+            &[("X-Forwarded-For", "203.0.113.7")],
+            "127.0.0.1",
+            "http://localhost:{port}",
+        ),
+        (
+            &real_ip,
+            &[
+                ("X-Real-IP", "198.51.100.9"),
+                ("X-Forwarded-For", "203.0.113.7"),
+            ],
+            "198.51.100.9",
+            "http://localhost:{port}",
+        ),
+    ];
+
+    for (trusted, headers, ip, origin) in cases {
+        for (server, expected_ip, expected_origin) in [
+            (
+                trusted,
+                ip,
+                origin.replace("{port}", &trusted.port.to_string()),
+            ),
+            (
+                &untrusted,
+                "127.0.0.1",
+                format!("http://localhost:{}", untrusted.port),
+            ),
+        ] {
+            let res = server
+                .get("/client", |req| {
+                    headers
+                        .iter()
+                        .fold(req, |req, (name, value)| req.header(*name, *value))
+                })
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(
+                res.text().await.unwrap(),
+                format!("{} {}", expected_ip, expected_origin)
+            );
+        }
+    }
+
+    for server in [forwarded_for, forwarded, real_ip] {
+        let errors = server.stop().await;
+        assert_no_server_errors!(errors);
+    }
+
+    let errors = untrusted.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_proxy_protocol() {
+    use tokio::io::AsyncWriteExt;
+
+    #[handler]
+    async fn final_handler(info: ConnectInfo, client_ip: ClientIp) -> String {
+        let proxy = match info.proxy {
+            Some(proxy) => proxy.remote_addr.to_string(),
+            None => "direct".to_owned(),
+        };
+
+        format!(
+            "{} {} {} {}",
+            info.remote_addr, info.local_addr, *client_ip, proxy
+        )
+    }
+
+    async fn connect(addr: std::net::SocketAddr, header: &[u8]) -> tokio::net::TcpStream {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(header).await.unwrap();
+        stream
+    }
+
+    let mut v2_header = b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x0c".to_vec();
+    v2_header.extend_from_slice(&[192, 0, 2, 1, 198, 51, 100, 2, 0x30, 0x39, 0x01, 0xbb]);
+    let mut v2_datagram_header = v2_header.clone();
+    v2_datagram_header[13] = 0x12;
+
+    let (shutdown_sender, shutdown_receiver) = shutdown_signal();
+    let listener = ProxyProtocolListener::new(TcpListener::bind("127.0.0.1:0").await.unwrap())
+        .with_header_timeout(Duration::from_millis(200));
+    let Addr::Tcp(addr) = Listener::local_addr(&listener).unwrap() else {
+        panic!("expected a TCP address");
+    };
+    let server = Server::new()
+        .get("/test", final_handler)
+        .with_graceful_shutdown(shutdown_receiver);
+    let serve_task = spawn(server.serve_with(listener));
+
+    let v1 = connect(addr, b"PROXY TCP4 192.0.2.1 198.51.100.2 12345 443\r\n").await;
+    let (status, body) = http1_get(v1, "/test").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(
+        body.starts_with("192.0.2.1:12345 198.51.100.2:443 192.0.2.1 127.0.0.1:"),
+        "{}",
+        body
+    );
+
+    let v1_ipv6 = connect(addr, b"PROXY TCP6 2001:db8::1 2001:db8::2 1 2\r\n").await;
+    let (status, body) = http1_get(v1_ipv6, "/test").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.starts_with("[2001:db8::1]:1 [2001:db8::2]:2 2001:db8::1 "));
+
+    let v2 = connect(addr, &v2_header).await;
+    let (status, body) = http1_get(v2, "/test").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.starts_with("192.0.2.1:12345 198.51.100.2:443 192.0.2.1 127.0.0.1:"));
+
+    let unknown = connect(addr, b"PROXY UNKNOWN\r\n").await;
+    let (status, body) = http1_get(unknown, "/test").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.ends_with(" 127.0.0.1 direct"), "{}", body);
+
+    let rejected: [&[u8]; 5] = [
+        b"GET /test HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        b"PROXY TCP4 192.0.2.1 198.51.100.2 012345 443\r\n",
+        b"PROXY TCP4 2001:db8::1 198.51.100.2 1 2\r\n",
+        b"\r\n\r\n\0\r\nQUIT\n\x31\x11\x00\x00",
+        &v2_datagram_header,
+    ];
+
+    for header in rejected {
+        let mut stream = connect(addr, header).await;
+        stream
+            .write_all(b"GET /test HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        assert_eq!(read_response_head(&mut stream).await, "");
+    }
+
+    // Clients that never finish the header are dropped after the timeout.
+    let mut stream = connect(addr, b"PROXY TCP4 ").await;
+    assert_eq!(read_response_head(&mut stream).await, "");
+
+    shutdown_sender.shutdown().await;
+    serve_task.await.unwrap().unwrap();
+
+    let listener = ProxyProtocolListener::new(TcpListener::bind("127.0.0.1:0").await.unwrap())
+        .with_mode(ProxyProtocolMode::Optional);
+    let Addr::Tcp(addr) = Listener::local_addr(&listener).unwrap() else {
+        panic!("expected a TCP address");
+    };
+    let (shutdown_sender, shutdown_receiver) = shutdown_signal();
+    let server = Server::new()
+        .get("/test", final_handler)
+        .with_graceful_shutdown(shutdown_receiver);
+    let serve_task = spawn(server.serve_with(listener));
+
+    let direct = connect(addr, b"").await;
+    let (status, body) = http1_get(direct, "/test").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.starts_with("127.0.0.1:"));
+    assert!(body.ends_with(" 127.0.0.1 direct"));
+
+    let v2 = connect(addr, &v2_header).await;
+    let (status, body) = http1_get(v2, "/test").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.starts_with("192.0.2.1:12345 "));
+
+    let mut malformed = connect(addr, b"PROXY TCP5 a b c d\r\n").await;
+    assert_eq!(read_response_head(&mut malformed).await, "");
+
+    shutdown_sender.shutdown().await;
+    serve_task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_inherited_listener_from_fd() {
+    #[handler]
+    async fn final_handler(req: Request) -> String {
+        req.peer_info().local_addr().to_string()
+    }
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let listener = InheritedListener::from_fd(listener.into()).unwrap();
+    assert!(matches!(listener, InheritedListener::Tcp(_)));
+
+    let (shutdown_sender, shutdown_receiver) = shutdown_signal();
+    let server = Server::new()
+        .get("/test", final_handler)
+        .with_graceful_shutdown(shutdown_receiver);
+    let serve_task = spawn(server.serve_with(listener));
+
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let (status, body) = http1_get(stream, "/test").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, addr.to_string());
+
+    shutdown_sender.shutdown().await;
+    serve_task.await.unwrap().unwrap();
+
+    let dir = std::env::temp_dir().join(format!("rum-from-fd-{}", std::process::id()));
+    _ = std::fs::remove_file(&dir);
+    let listener = std::os::unix::net::UnixListener::bind(&dir).unwrap();
+    let listener = InheritedListener::from_fd(listener.into()).unwrap();
+    assert_eq!(
+        Listener::local_addr(&listener).unwrap(),
+        Addr::Unix(Some(dir.clone()))
+    );
+    drop(listener);
+    std::fs::remove_file(&dir).unwrap();
+
+    let not_listening = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let err = InheritedListener::from_fd(not_listening.into()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[tokio::test]
+async fn test_listener_handoff() {
+    const CHILD_VAR: &str = "RUM_TEST_HANDOFF_CHILD";
+
+    #[handler]
+    async fn parent_handler() -> &'static str {
+        "parent"
+    }
+
+    #[handler]
+    async fn child_handler() -> &'static str {
+        "child"
+    }
+
+    #[handler]
+    async fn stop_handler(shutdown_sender: State<ShutdownSender>) -> StatusCode {
+        let shutdown_sender = shutdown_sender.clone();
+        spawn(async move { shutdown_sender.shutdown().await });
+        StatusCode::OK
+    }
+
+    if std::env::var_os(CHILD_VAR).is_some() {
+        let mut inherited = InheritedListeners::from_env();
+        assert_eq!(inherited.len(), 1);
+        assert_eq!(inherited.name(0), Some("http"));
+        let listener = inherited.take_named("http").unwrap().unwrap();
+        assert!(inherited.take(0).unwrap().is_none());
+
+        let (shutdown_sender, shutdown_receiver) = shutdown_signal();
+        let server = Server::new()
+            .get("/test", child_handler)
+            .get("/stop", stop_handler)
+            .with_state(shutdown_sender)
+            .with_graceful_shutdown(shutdown_receiver);
+        server.serve_with(listener).await.unwrap();
+        return;
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handoff = ListenerHandoff::new()
+        .with_named_listener(&listener, "http")
+        .unwrap();
+    let server = Server::new()
+        .get("/test", parent_handler)
+        .bind_with(listener)
+        .await
+        .unwrap();
+
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    assert_eq!(http1_get(stream, "/test").await.1, "parent");
+
+    let mut command = std::process::Command::new(std::env::current_exe().unwrap());
+    command
+        .args(["test_listener_handoff", "--exact"])
+        .env(CHILD_VAR, "1")
+        .stdout(std::process::Stdio::null());
+    let mut child = handoff.spawn(command).unwrap();
+    server.shutdown().await;
+
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    assert_eq!(http1_get(stream, "/test").await.1, "child");
+
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    assert_eq!(http1_get(stream, "/stop").await.0, StatusCode::OK);
+    assert!(tokio::task::spawn_blocking(move || child.wait())
+        .await
+        .unwrap()
+        .unwrap()
+        .success());
+}
+
+#[tokio::test]
+async fn test_acceptors() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[handler]
+    async fn final_handler(counter: State<Arc<AtomicUsize>>) -> String {
+        counter.fetch_add(1, Ordering::Relaxed);
+        format!("{:?}", std::thread::current().id())
+    }
+
+    let counter = Arc::new(AtomicUsize::new(0));
+    let server = TestServer::new()
+        .config(|server| {
+            server
+                .get("/test", final_handler)
+                .with_state(Arc::clone(&counter))
+                .with_acceptors(4)
+                .with_acceptor_threads(true)
+                .with_socket_config(SocketConfig::new().backlog(64).nodelay(true))
+        })
+        .start()
+        .await
+        .unwrap();
+
+    let mut threads = HashSet::new();
+
+    for _ in 0..32 {
+        let stream = tokio::net::TcpStream::connect(("127.0.0.1", server.port))
+            .await
+            .unwrap();
+        let (status, body) = http1_get(stream, "/test").await;
+        assert_eq!(status, StatusCode::OK);
+        threads.insert(body);
+    }
+
+    assert!(threads.len() > 1);
+    assert!(!threads.contains(&format!("{:?}", std::thread::current().id())));
+    assert_eq!(counter.load(Ordering::Relaxed), 32);
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+
+    let config = SocketConfig::new()
+        .nodelay(true)
+        .keepalive(Duration::from_secs(60));
+    let mut listener = SocketListener::bind("127.0.0.1:0", config).await.unwrap();
+    let Addr::Tcp(addr) = Listener::local_addr(&listener).unwrap() else {
+        panic!("expected a TCP address");
+    };
+    let (client, accepted) = tokio::join!(tokio::net::TcpStream::connect(addr), async {
+        Listener::accept(&mut listener).await
+    });
+    let (conn, peer) = accepted.unwrap();
+    assert!(conn.nodelay().unwrap());
+    assert_eq!(
+        peer.remote_addr(),
+        &Addr::Tcp(client.unwrap().local_addr().unwrap())
+    );
+}
+
+#[tokio::test]
+async fn test_max_requests() {
+    #[handler]
+    async fn slow_handler() -> &'static str {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        "done"
+    }
+
+    let server = TestServer::new()
+        .config(|server| {
+            server
+                .get("/test", slow_handler)
+                .with_max_requests(1)
+                .with_overload_retry_after(Duration::from_millis(1500))
+        })
+        .start()
+        .await
+        .unwrap();
+
+    let (first, second) = tokio::join!(server.get("/test", |req| req), async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        server.get("/test", |req| req).await
+    });
+    let (first, second) = (first.unwrap(), second.unwrap());
+    assert_eq!(first.status(), StatusCode::OK);
+    assert_eq!(first.text().await.unwrap(), "done");
+    assert_eq!(second.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(second.headers()["Retry-After"], "2");
+
+    let res = server.get("/test", |req| req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_max_connections() {
+    use tokio::io::AsyncWriteExt;
+
+    #[handler]
+    async fn final_handler() -> &'static str {
+        "Success"
+    }
+
+    let server = TestServer::new()
+        .config(|server| server.get("/test", final_handler).with_max_connections(1))
+        .start()
+        .await
+        .unwrap();
+
+    let request = b"GET /test HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let mut first = tokio::net::TcpStream::connect(("127.0.0.1", server.port))
+        .await
+        .unwrap();
+    first.write_all(request).await.unwrap();
+    assert!(read_response_head(&mut first)
+        .await
+        .starts_with("HTTP/1.1 200 "));
+
+    let mut second = tokio::net::TcpStream::connect(("127.0.0.1", server.port))
+        .await
+        .unwrap();
+    second.write_all(request).await.unwrap();
+    let pending =
+        tokio::time::timeout(Duration::from_millis(200), read_response_head(&mut second)).await;
+    assert!(pending.is_err());
+
+    drop(first);
+    assert!(read_response_head(&mut second)
+        .await
+        .starts_with("HTTP/1.1 200 "));
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_max_connections_tls() {
+    #[handler]
+    async fn final_handler() -> &'static str {
+        "Success"
+    }
+
+    let (cert, key) = self_signed_cert();
+    let tls_config = TlsConfig::from_pem(cert.as_bytes(), key.as_bytes()).unwrap();
+    let listener = TlsListener::new(TcpListener::bind("127.0.0.1:0").await.unwrap(), tls_config)
+        .with_max_handshakes(1);
+
+    let server = TestServer::new()
+        .config(|server| server.get("/test", final_handler).with_max_connections(1))
+        .start_tls_listener(listener, &cert)
+        .await
+        .unwrap();
+    let url = format!("https://localhost:{}/test", server.port);
+
+    let first = tls_client(&cert);
+    let res = first.get(&url).send().await.unwrap();
+    assert_eq!(res.text().await.unwrap(), "Success");
+
+    let second = tls_client(&cert);
+    let pending = tokio::time::timeout(Duration::from_millis(200), second.get(&url).send()).await;
+    assert!(pending.is_err());
+
+    drop(first);
+    let res = tokio::time::timeout(Duration::from_secs(5), second.get(&url).send())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(res.text().await.unwrap(), "Success");
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_accept_error_backoff() {
+    struct FailingListener {
+        inner: TcpListener,
+        failures: usize,
+    }
+
+    impl Listener for FailingListener {
+        type Io = tokio::net::TcpStream;
+
+        async fn accept(&mut self) -> io::Result<(Self::Io, PeerInfo)> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(io::Error::other("too many open files"));
+            }
+
+            Listener::accept(&mut self.inner).await
+        }
+
+        fn local_addr(&self) -> io::Result<Addr> {
+            Listener::local_addr(&self.inner)
+        }
+    }
+
+    #[handler]
+    async fn final_handler() -> &'static str {
+        "Success"
+    }
+
+    let listener = FailingListener {
+        inner: TcpListener::bind("127.0.0.1:0").await.unwrap(),
+        failures: 4,
+    };
+    let (error_sender, mut error_receiver) = error_report_stream();
+    let start = Instant::now();
+    let server = Server::new()
+        .get("/test", final_handler)
+        .with_error_reporting(error_sender)
+        .bind_with(listener)
+        .await
+        .unwrap();
+    let Addr::Tcp(addr) = server.local_addr().clone() else {
+        panic!("expected a TCP address");
+    };
+
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let (status, body) = http1_get(stream, "/test").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "Success");
+    // The loop backs off for 5, 10, 20 and 40 milliseconds.
+    assert!(start.elapsed() >= Duration::from_millis(75));
+
+    server.shutdown().await;
+    let mut errors = Vec::new();
+
+    while let Some(err) = error_receiver.next().await {
+        errors.push(err);
+    }
+
+    assert_eq!(errors.len(), 4);
+    assert!(errors.iter().all(
+        |err| matches!(&**err, Error::AcceptError(err) if err.to_string() == "too many open files")
+    ));
+}
+
+#[tokio::test]
+async fn test_server_config() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[handler]
+    async fn final_handler() -> &'static str {
+        "Success"
+    }
+
+    let server = TestServer::new()
+        .config(|server| {
+            server.get("/test", final_handler).with_config(
+                ServerConfig::new()
+                    .keep_alive(false)
+                    .max_headers(8)
+                    .max_header_size(1024)
+                    .server_header("rum")
+                    .date_header(false),
+            )
+        })
+        .start()
+        .await
+        .unwrap();
+
+    let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", server.port))
+        .await
+        .unwrap();
+    let headers = (0..10)
+        .map(|i| format!("X-Test-{i}: {i}\r\n"))
+        .collect::<String>();
+    let request = format!("GET /test HTTP/1.1\r\nHost: localhost\r\n{headers}\r\n");
+    stream.write_all(request.as_bytes()).await.unwrap();
+    assert!(read_response_head(&mut stream)
+        .await
+        .starts_with("HTTP/1.1 431 "));
+
+    let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", server.port))
+        .await
+        .unwrap();
+    let request = format!(
+        "GET /test HTTP/1.1\r\nHost: localhost\r\nX-Test: {}\r\n\r\n",
+        "a".repeat(2000)
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    assert!(read_response_head(&mut stream)
+        .await
+        .starts_with("HTTP/1.1 431 "));
+
+    let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", server.port))
+        .await
+        .unwrap();
+    stream
+        .write_all(b"GET /test HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let head = read_response_head(&mut stream).await.to_lowercase();
+    assert!(head.starts_with("http/1.1 200 "));
+    assert!(head.contains("\r\nserver: rum\r\n"));
+    assert!(head.contains("\r\nconnection: close\r\n"));
+    assert!(!head.contains("\r\ndate: "));
+    let mut body = String::new();
+    stream.read_to_string(&mut body).await.unwrap();
+    assert_eq!(body, "Success");
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_idle_timeout() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[handler]
+    async fn final_handler() -> &'static str {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        "Success"
+    }
+
+    let server = TestServer::new()
+        .config(|server| {
+            server
+                .get("/test", final_handler)
+                .with_config(ServerConfig::new().idle_timeout(Duration::from_millis(200)))
+        })
+        .start()
+        .await
+        .unwrap();
+
+    let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", server.port))
+        .await
+        .unwrap();
+    stream
+        .write_all(b"GET /test HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    // The handler takes longer than the idle timeout, but the connection is
+    // not idle while it runs.
+    assert!(read_response_head(&mut stream)
+        .await
+        .starts_with("HTTP/1.1 200 "));
+    let mut body = [0; 7];
+    stream.read_exact(&mut body).await.unwrap();
+    assert_eq!(&body, b"Success");
+
+    let start = Instant::now();
+    let closed = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut [0]))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(closed, 0);
+    assert!(start.elapsed() >= Duration::from_millis(150));
+
+    let errors = server.stop().await;
+    assert_no_server_errors!(errors);
+}
+
+#[tokio::test]
+async fn test_event_stream() {
+    use tokio::io::AsyncWriteExt;
+
+    #[handler]
+    async fn user_handler(id: PathParam<"id", u32>) -> String {
+        id.to_string()
+    }
+
+    #[handler]
+    async fn failing_handler() -> Result<&'static str> {
+        Err(Error::NoNextFunction)
+    }
+
+    #[handler]
+    async fn upload_handler(body: BodyRaw) -> BodyRaw {
+        body
+    }
+
+    let (event_sender, mut event_receiver) = event_stream();
+    let server = Server::new()
+        .get("/users/{id}", user_handler)
+        .get("/fail", failing_handler)
+        .post("/upload", upload_handler)
+        .with_event_reporting(event_sender)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap();
+    let Addr::Tcp(addr) = server.local_addr().clone() else {
+        panic!("expected a TCP address");
+    };
+
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let local_addr = stream.local_addr().unwrap();
+    let (status, body) = http1_get(stream, "/users/7").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "7");
+
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let (status, _) = http1_get(stream, "/fail").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+    // The client gives up partway through the body.
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(
+            b"POST /upload HTTP/1.1\r\nHost: localhost\r\nContent-Length: 100\r\n\r\npartial",
+        )
+        .await
+        .unwrap();
+    stream.shutdown().await.unwrap();
+    assert_eq!(read_response_head(&mut stream).await, "");
+
+    server.shutdown().await;
+    let mut events = Vec::new();
+
+    while let Some(event) = event_receiver.next().await {
+        events.push(event);
+    }
+
+    let ServerEvent::ConnectionAccepted {
+        connection_id,
+        remote_addr,
+        ..
+    } = &events[0]
+    else {
+        panic!("expected a connection to be accepted");
+    };
+    assert_eq!(*remote_addr, Addr::Tcp(local_addr));
+    let ServerEvent::RequestStarted(request) = &events[1] else {
+        panic!("expected a request to start");
+    };
+    assert_eq!(request.connection_id, *connection_id);
+    assert_eq!(request.method, Method::GET);
+    assert_eq!(request.path, "/users/7");
+    assert_eq!(request.route, Some(RoutePath::from("/users/{id}")));
+    assert!(matches!(
+        &events[2],
+        ServerEvent::RequestFinished { request: finished, status: StatusCode::OK, .. }
+            if finished == request
+    ));
+
+    let handler_errors = events
+        .iter()
+        .filter_map(|event| match event {
+            ServerEvent::HandlerError { request, error } => Some((request, error)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(handler_errors.len(), 1);
+    assert_eq!(handler_errors[0].0.route, Some(RoutePath::from("/fail")));
+    assert!(matches!(**handler_errors[0].1, Error::NoNextFunction));
+    assert!(events.iter().any(|event| matches!(
+        event,
+        ServerEvent::RequestFinished {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            ..
+        }
+    )));
+
+    let started = events
+        .iter()
+        .filter(|event| matches!(event, ServerEvent::RequestStarted(_)))
+        .count();
+    let ended = events
+        .iter()
+        .filter(|event| {
+            matches!(
+                event,
+                ServerEvent::RequestFinished { .. } | ServerEvent::RequestFailed { .. }
+            )
+        })
+        .count();
+    assert_eq!(started, 3);
+    assert_eq!(ended, 3);
+    let failed = events
+        .iter()
+        .filter_map(|event| match event {
+            ServerEvent::RequestFailed { request, error, .. } => Some((request, error)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].0.method, Method::POST);
+    assert_eq!(failed[0].0.route, Some(RoutePath::from("/upload")));
+    assert!(matches!(**failed[0].1, Error::ServerError(_)));
+
+    let closed = |id| {
+        events.iter().any(|event| {
+            matches!(event, ServerEvent::ConnectionClosed { connection_id, .. } if *connection_id == id)
+        })
+    };
+    assert!(closed(*connection_id));
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| matches!(event, ServerEvent::ConnectionClosed { .. }))
+            .count(),
+        3
+    );
+}